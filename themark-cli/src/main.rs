@@ -1,7 +1,8 @@
 mod path_resolver;
 
 use path_resolver::DocumentPath;
-use themark_fs::{load_markdown, read_curr_dir, read_dir};
+use themark_fs::{cache_dir, load_config, load_markdown, read_curr_dir, read_dir, syntaxes_dir};
+use themark_parser::syntax::{load_syntaxes, theme_names, Highlighter, SyntaxTheme};
 
 use clap::Parser;
 use themark_ui::Entrypoint;
@@ -10,14 +11,51 @@ use themark_ui::Entrypoint;
 #[command(version, about = "Render markdown files on your terminal", long_about = None)]
 struct Args {
     path: Option<String>,
+    /// Theme to highlight code with, either the name of a built-in theme or a path to a
+    /// .tmTheme or .stTheme file
+    #[arg(long)]
+    syntax_theme: Option<String>,
+    /// List the built-in syntax themes
+    #[arg(long)]
+    list_syntax_themes: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    if args.list_syntax_themes {
+        for name in theme_names() {
+            println!("{name}");
+        }
+        return Ok(());
+    }
+
+    let config = load_config()?;
+    if let Some(theme) = args.syntax_theme.or(config.syntax_theme) {
+        Highlighter::shared().set_theme(SyntaxTheme::load(&theme)?);
+    }
+
+    if let Some(dir) = syntaxes_dir() {
+        let cache = cache_dir().map(|dir| dir.join("syntaxes.packdump"));
+        // Code is still highlighted with the built-in syntaxes when these cannot be loaded.
+        match load_syntaxes(&dir, cache.as_deref()) {
+            Ok(Some(user)) => {
+                for skipped in user.skipped {
+                    eprintln!("warning: skipped syntax {skipped}");
+                }
+                Highlighter::shared().set_syntaxes(user.syntaxes);
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!("warning: could not load syntaxes: {error}"),
+        }
+    }
+
     let entrypoint = match args.path {
         Some(path) => match DocumentPath::try_from(path)? {
-            DocumentPath::File(path) => Entrypoint::Viewer(load_markdown(path)?),
+            DocumentPath::File(path) => {
+                let document = load_markdown(&path)?;
+                Entrypoint::Viewer(path, document)
+            }
             DocumentPath::Dir(path) => Entrypoint::Dashboard(read_dir(path)?),
             //DocumentPath::Uri(uri) => load_markdown(fetch_markdown(uri)?)?,
         },
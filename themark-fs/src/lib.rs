@@ -1,11 +1,35 @@
-use std::fs::DirEntry;
-use std::path::Path;
+use std::fs::{DirEntry, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use themark_parser::{parse, Token};
+use themark_parser::syntax::Highlighter;
+use themark_parser::{parse, parse_title, Document};
 
-pub fn load_markdown<P: AsRef<Path>>(path: P) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+pub fn load_markdown<P: AsRef<Path>>(path: P) -> Result<Document, Box<dyn std::error::Error>> {
     let contents = read_file(path)?;
-    Ok(parse(&contents))
+    Ok(parse(&contents, Highlighter::shared()))
+}
+
+/// How much of a document is read to find its title, which is in its front matter or first
+/// heading near the top of it.
+const TITLE_READ_LIMIT: u64 = 16 * 1024;
+
+pub fn read_title<P: AsRef<Path>>(path: P) -> Option<String> {
+    let mut bytes = vec![];
+    File::open(path.as_ref())
+        .ok()?
+        .take(TITLE_READ_LIMIT)
+        .read_to_end(&mut bytes)
+        .ok()?;
+
+    // A line cut off by the limit could be taken for something it is not, so it is dropped.
+    if bytes.len() as u64 == TITLE_READ_LIMIT {
+        let end = bytes.iter().rposition(|b| *b == b'\n')?;
+        bytes.truncate(end + 1);
+    }
+
+    parse_title(&String::from_utf8_lossy(&bytes))
 }
 
 fn read_file<P: AsRef<Path>>(path: P) -> Result<String, Box<dyn std::error::Error>> {
@@ -54,3 +78,166 @@ pub fn read_dir<P: AsRef<Path>, T: From<DirEntry>>(
 fn is_markdown<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().extension().is_some_and(|ext| ext == "md")
 }
+
+/// Where a link inside of a document points to.
+#[derive(Debug, PartialEq)]
+pub enum LinkTarget {
+    /// Slug of a heading in the same document, without the leading `#`.
+    Fragment(String),
+    /// Markdown file on disk, along with the heading linked to in it, if any.
+    Markdown {
+        path: PathBuf,
+        fragment: Option<String>,
+    },
+    /// Anything else, such as a URL or a file that is not markdown.
+    External(String),
+}
+
+fn has_scheme(uri: &str) -> bool {
+    // A single letter before the colon is a Windows drive rather than a scheme.
+    uri.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Resolves `uri` as it appears in the document at `document`, relative paths being relative
+/// to the directory of that document.
+pub fn resolve_link<P: AsRef<Path>>(document: P, uri: &str) -> LinkTarget {
+    if let Some(fragment) = uri.strip_prefix('#') {
+        return LinkTarget::Fragment(decode_fragment(fragment));
+    }
+
+    if has_scheme(uri) {
+        return LinkTarget::External(uri.to_string());
+    }
+
+    let (path, fragment) = match uri.split_once('#') {
+        Some((path, fragment)) => (path, Some(decode_fragment(fragment))),
+        None => (uri, None),
+    };
+    let path = percent_decode(path);
+    let path = match document.as_ref().parent() {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+
+    match is_markdown(&path) && path.is_file() {
+        true => LinkTarget::Markdown { path, fragment },
+        false => LinkTarget::External(path.to_string_lossy().to_string()),
+    }
+}
+
+/// Decodes a fragment into the slug of the heading it links to, which are all lowercase.
+fn decode_fragment(fragment: &str) -> String {
+    percent_decode(fragment).to_lowercase()
+}
+
+/// Decodes the `%XX` escapes in `uri`, like `%20` for a space, leaving any `%` that does not
+/// start an escape as it is.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|hex| bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn default_opener() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "open",
+        "windows" => "explorer",
+        _ => "xdg-open",
+    }
+}
+
+/// Opens `target` with the command in `THEMARK_OPENER`, falling back to the platform's own
+/// opener. The command may include arguments, the target is passed after them. The opener is
+/// waited on in the background, so it is not left behind as a zombie once it exits.
+pub fn open_external(target: &str) -> std::io::Result<()> {
+    let opener = std::env::var("THEMARK_OPENER").unwrap_or_else(|_| default_opener().into());
+    let mut args = opener.split_whitespace();
+    let program = args.next().unwrap_or(default_opener());
+
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Directory themark reads its configuration from, `$XDG_CONFIG_HOME/themark` or else
+/// `~/.config/themark`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("themark"))
+}
+
+/// Directory themark caches files in, `$XDG_CACHE_HOME/themark` or else `~/.cache/themark`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache.join("themark"))
+}
+
+/// Directory `.sublime-syntax` files are loaded from, to highlight languages themark does not
+/// know about.
+pub fn syntaxes_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("syntaxes"))
+}
+
+/// Settings read from `config.toml` in the [`config_dir`], all of them optional.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Name of a syntax theme built into themark, or path to a theme file.
+    pub syntax_theme: Option<String>,
+}
+
+pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+    let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+        return Ok(Config::default());
+    };
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let table = contents.parse::<toml::Table>()?;
+    Ok(Config {
+        syntax_theme: table
+            .get("syntax_theme")
+            .and_then(toml::Value::as_str)
+            .map(String::from),
+    })
+}
@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use themark_parser::parse;
+use themark_parser::syntax::Highlighter;
+
+const SNIPPETS: [(&str, &str); 4] = [
+    (
+        "rs",
+        "fn main() {\n    let names = vec![\"a\", \"b\"];\n    for name in names {\n        println!(\"{name}\");\n    }\n}\n",
+    ),
+    (
+        "py",
+        "def main():\n    names = [\"a\", \"b\"]\n    for name in names:\n        print(name)\n",
+    ),
+    (
+        "js",
+        "function main() {\n  const names = [\"a\", \"b\"];\n  names.forEach((name) => console.log(name));\n}\n",
+    ),
+    ("sh", "for name in a b; do\n  echo \"$name\"\ndone\n"),
+];
+
+/// Document with 50 code blocks, about as many as a long tutorial has.
+fn code_heavy_document() -> String {
+    (0..50)
+        .map(|index| {
+            let (language, code) = SNIPPETS[index % SNIPPETS.len()];
+            format!("## Step {index}\n\nRun the following.\n\n```{language}\n{code}```\n\n")
+        })
+        .collect()
+}
+
+fn highlight(c: &mut Criterion) {
+    let document = code_heavy_document();
+    let mut group = c.benchmark_group("code heavy document");
+    group.sample_size(10);
+
+    // What parsing used to do, loading the syntaxes and theme again for every code block.
+    group.bench_function("highlighter per code block", |b| {
+        b.iter(|| {
+            for (language, code) in SNIPPETS.iter().cycle().take(50) {
+                Highlighter::new().highlight(code, language);
+            }
+        })
+    });
+    group.bench_function("highlighter per document", |b| {
+        b.iter(|| parse(&document, &Highlighter::new()))
+    });
+    group.bench_function("shared highlighter", |b| {
+        b.iter(|| parse(&document, Highlighter::shared()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, highlight);
+criterion_main!(benches);
@@ -0,0 +1,119 @@
+use pulldown_cmark::MetadataBlockKind;
+
+type Fields = Vec<(String, String)>;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    /// Every top level field in the order it was written, formatted for display.
+    pub fields: Fields,
+}
+
+impl FrontMatter {
+    /// Parses the contents of a metadata block, which is YAML when delimited by `---` and TOML
+    /// when delimited by `+++`. Malformed front matter is ignored rather than failing the
+    /// document.
+    pub fn parse(source: &str, kind: MetadataBlockKind) -> Option<FrontMatter> {
+        let (fields, tags) = match kind {
+            MetadataBlockKind::YamlStyle => parse_yaml(source)?,
+            MetadataBlockKind::PlusesStyle => parse_toml(source)?,
+        };
+
+        let mut front_matter = FrontMatter {
+            fields,
+            tags,
+            ..Default::default()
+        };
+        front_matter.title = front_matter.field("title");
+        front_matter.author = front_matter.field("author");
+        front_matter.date = front_matter.field("date");
+
+        Some(front_matter)
+    }
+
+    fn field(&self, key: &str) -> Option<String> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.clone())
+    }
+}
+
+fn yaml_to_string(value: &serde_norway::Value) -> String {
+    match value {
+        serde_norway::Value::Null => String::new(),
+        serde_norway::Value::Bool(b) => b.to_string(),
+        serde_norway::Value::Number(n) => n.to_string(),
+        serde_norway::Value::String(s) => s.clone(),
+        serde_norway::Value::Sequence(seq) => seq
+            .iter()
+            .map(yaml_to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        serde_norway::Value::Mapping(_) | serde_norway::Value::Tagged(_) => {
+            serde_norway::to_string(value)
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        }
+    }
+}
+
+fn toml_to_string(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Datetime(datetime) => datetime.to_string(),
+        toml::Value::Array(arr) => arr
+            .iter()
+            .map(toml_to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        value => value.to_string(),
+    }
+}
+
+fn parse_yaml(source: &str) -> Option<(Fields, Vec<String>)> {
+    let serde_norway::Value::Mapping(mapping) = serde_norway::from_str(source).ok()? else {
+        return None;
+    };
+
+    let mut tags = vec![];
+    let fields = mapping
+        .iter()
+        .map(|(key, value)| {
+            let key = yaml_to_string(key);
+            if key.eq_ignore_ascii_case("tags") {
+                tags = match value {
+                    serde_norway::Value::Sequence(seq) => seq.iter().map(yaml_to_string).collect(),
+                    value => vec![yaml_to_string(value)],
+                };
+            }
+            (key, yaml_to_string(value))
+        })
+        .collect();
+
+    Some((fields, tags))
+}
+
+fn parse_toml(source: &str) -> Option<(Fields, Vec<String>)> {
+    let table = source.parse::<toml::Table>().ok()?;
+
+    let mut tags = vec![];
+    let fields = table
+        .iter()
+        .map(|(key, value)| {
+            if key.eq_ignore_ascii_case("tags") {
+                tags = match value {
+                    toml::Value::Array(arr) => arr.iter().map(toml_to_string).collect(),
+                    value => vec![toml_to_string(value)],
+                };
+            }
+            (key.clone(), toml_to_string(value))
+        })
+        .collect();
+
+    Some((fields, tags))
+}
@@ -0,0 +1,208 @@
+use crate::{TextStyle, Token};
+
+#[derive(Debug, PartialEq)]
+pub enum HtmlPart<'a> {
+    Tag(HtmlTag<'a>),
+    Text(&'a str),
+    Comment,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HtmlTag<'a> {
+    pub name: String,
+    pub closing: bool,
+    pub source: &'a str,
+    attributes: &'a str,
+}
+
+impl<'a> HtmlTag<'a> {
+    /// Parses a single tag such as `<img src="logo.png">` or `</kbd>`.
+    pub fn parse(source: &'a str) -> Option<Self> {
+        let inner = source.trim().strip_prefix('<')?.strip_suffix('>')?;
+        let inner = inner.strip_suffix('/').unwrap_or(inner);
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(inner) => (true, inner),
+            None => (false, inner),
+        };
+
+        let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+        let name = &inner[..name_end];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_ascii_lowercase(),
+            closing,
+            source,
+            attributes: &inner[name_end..],
+        })
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<String> {
+        let mut rest = self.attributes;
+
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                return None;
+            }
+
+            let name_end = rest
+                .find(|c: char| c == '=' || c.is_whitespace())
+                .unwrap_or(rest.len());
+            let name = &rest[..name_end];
+            rest = rest[name_end..].trim_start();
+
+            let value = match rest.strip_prefix('=').map(str::trim_start) {
+                Some(value) => {
+                    let (value, remaining) = match value.chars().next() {
+                        Some(quote @ ('"' | '\'')) => {
+                            let value = &value[1..];
+                            let end = value.find(quote).unwrap_or(value.len());
+                            (&value[..end], value.get(end + 1..).unwrap_or_default())
+                        }
+                        _ => {
+                            let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                            (&value[..end], &value[end..])
+                        }
+                    };
+                    rest = remaining;
+                    value
+                }
+                None => "",
+            };
+
+            if name.eq_ignore_ascii_case(key) {
+                return Some(value.to_string());
+            }
+        }
+    }
+}
+
+/// Splits a chunk of HTML into tags, comments and the text between them.
+pub fn split(source: &str) -> Vec<HtmlPart<'_>> {
+    let mut parts = vec![];
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").map_or(comment.len(), |idx| idx + 3);
+            parts.push(HtmlPart::Comment);
+            rest = &comment[end..];
+            continue;
+        }
+
+        if rest.starts_with('<') {
+            let tag = rest
+                .find('>')
+                .and_then(|end| HtmlTag::parse(&rest[..=end]).map(|tag| (tag, end)));
+
+            if let Some((tag, end)) = tag {
+                parts.push(HtmlPart::Tag(tag));
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| *c == '<')
+            .map_or(rest.len(), |(idx, _)| idx);
+        parts.push(HtmlPart::Text(&rest[..end]));
+        rest = &rest[end..];
+    }
+
+    parts
+}
+
+/// Lowers a single tag into a token, updating `style` for tags that style the text after
+/// them. Tags without a sensible rendering are kept as raw HTML.
+pub fn lower_tag(tag: &HtmlTag<'_>, style: &mut TextStyle) -> Option<Token> {
+    let enabled = !tag.closing;
+
+    match tag.name.as_str() {
+        "br" => Some(Token::Text {
+            content: String::from("\n"),
+            style: *style,
+        }),
+        "img" if enabled => Some(Token::Image {
+            uri: tag.attribute("src").unwrap_or_default(),
+            text: tag.attribute("alt").unwrap_or_default(),
+        }),
+        "kbd" => {
+            style.keyboard = enabled;
+            None
+        }
+        "b" | "strong" => {
+            style.bold = enabled;
+            None
+        }
+        "i" | "em" => {
+            style.italic = enabled;
+            None
+        }
+        "s" | "del" => {
+            style.strikethrough = enabled;
+            None
+        }
+        _ => Some(Token::Html(tag.source.to_string())),
+    }
+}
+
+/// Lowers HTML into inline tokens, collapsing the line breaks between tags like a browser would.
+pub fn lower_inline(source: &str, style: &mut TextStyle) -> Vec<Token> {
+    let mut parts = vec![];
+
+    for part in split(source) {
+        match part {
+            HtmlPart::Tag(tag) => parts.extend(lower_tag(&tag, style)),
+            HtmlPart::Text(text) if text.trim().is_empty() && text.contains('\n') => {}
+            HtmlPart::Text(text) => parts.push(Token::Text {
+                content: text.replace('\n', " "),
+                style: *style,
+            }),
+            HtmlPart::Comment => {}
+        }
+    }
+
+    parts
+}
+
+/// Extracts the text inside of `<summary>` from the opening block of a `<details>` section,
+/// returning it together with the HTML that comes after it.
+pub fn take_summary(source: &str) -> (String, &str) {
+    // Lowercasing ASCII keeps byte offsets intact, so they can be used to slice `source`.
+    let lowercase = source.to_ascii_lowercase();
+    let tag_end = |from: usize| {
+        lowercase[from..]
+            .find('>')
+            .map_or(source.len(), |idx| from + idx + 1)
+    };
+
+    let Some(start) = lowercase.find("<summary") else {
+        let details = lowercase.find("<details").unwrap_or_default();
+        return (String::from("Details"), &source[tag_end(details)..]);
+    };
+
+    let content = tag_end(start);
+    let end = lowercase[content..]
+        .find("</summary")
+        .map_or(source.len(), |idx| content + idx);
+
+    let summary = split(&source[content..end])
+        .into_iter()
+        .filter_map(|part| match part {
+            HtmlPart::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect::<String>();
+
+    let rest = match end < source.len() {
+        true => &source[tag_end(end)..],
+        false => "",
+    };
+
+    (summary.trim().to_string(), rest)
+}
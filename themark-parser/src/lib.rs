@@ -1,16 +1,36 @@
+pub mod front_matter;
+pub mod html;
+pub mod slug;
 pub mod syntax;
 
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use front_matter::FrontMatter;
+use pulldown_cmark::{
+    Alignment, BlockQuoteKind, CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Options,
+    Parser, Tag, TagEnd,
+};
+use slug::Slugs;
+use syntax::Highlighter;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Document {
+    pub front_matter: Option<FrontMatter>,
+    pub tokens: Vec<Token>,
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
-    Text(String),
+    Text {
+        content: String,
+        style: TextStyle,
+    },
     Paragraph {
         parts: Vec<Token>,
     },
+    /// Table stored row by row, with every row holding as many cells as there are columns.
     Table {
-        headings: Vec<String>,
-        cols: Vec<Vec<String>>,
+        alignments: Vec<ColumnAlignment>,
+        header: Vec<TableCell>,
+        rows: Vec<Vec<TableCell>>,
     },
     Image {
         uri: String,
@@ -18,26 +38,172 @@ pub enum Token {
     },
     Heading {
         level: InnerHeadingLevel,
-        content: String,
+        parts: Vec<Token>,
+        /// GitHub compatible anchor of the heading, unique within the document.
+        slug: String,
     },
     List {
+        kind: ListKind,
         items: Vec<Token>,
     },
+    /// Link, numbered in the order links appear in the document.
     Link {
         uri: String,
-        label: String,
+        parts: Vec<Token>,
+        index: usize,
     },
     CodeBlock {
         content: Vec<syntax::CodeLine>,
         language: String,
+        /// Rest of the info string after the language, like `ignore` or `title="main.rs"`.
+        attributes: Vec<String>,
     },
     Code(String),
     ListItem {
-        parts: Vec<Token>,
+        children: Vec<Token>,
+        checked: Option<bool>,
+    },
+    BlockQuote {
+        children: Vec<Token>,
+    },
+    /// GitHub style alert, written as a quote starting with a marker like `[!NOTE]`.
+    Admonition {
+        kind: AdmonitionKind,
+        children: Vec<Token>,
+    },
+    /// Reference to a footnote, numbered by the order footnotes are first referenced in.
+    FootnoteReference {
+        label: String,
+        index: usize,
+    },
+    FootnoteDefinition {
+        label: String,
+        index: usize,
+        children: Vec<Token>,
     },
+    Rule,
+    /// Raw HTML that has no better rendering.
+    Html(String),
+    /// A collapsible `<details>` section.
+    Details {
+        summary: String,
+        children: Vec<Token>,
+    },
+}
+
+impl Token {
+    /// Text of an inline token, or of the inline content of a paragraph or heading, without
+    /// any of its styling.
+    pub fn plain_text(&self) -> String {
+        match self {
+            Token::Text { content, .. } | Token::Code(content) => content.clone(),
+            Token::Image { text, .. } => text.clone(),
+            Token::Paragraph { parts }
+            | Token::Heading { parts, .. }
+            | Token::Link { parts, .. } => parts.iter().map(Token::plain_text).collect(),
+            _ => String::new(),
+        }
+    }
+
+    /// Calls `f` with this token and then with every token nested inside of it.
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut Token)) {
+        f(self);
+
+        let children = match self {
+            Token::Paragraph { parts }
+            | Token::Heading { parts, .. }
+            | Token::Link { parts, .. } => parts,
+            Token::List { items, .. } => items,
+            Token::ListItem { children, .. }
+            | Token::BlockQuote { children }
+            | Token::Admonition { children, .. }
+            | Token::FootnoteDefinition { children, .. }
+            | Token::Details { children, .. } => children,
+            Token::Table { header, rows, .. } => {
+                for cell in header.iter_mut().chain(rows.iter_mut().flatten()) {
+                    for part in cell {
+                        part.walk_mut(f);
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        for child in children {
+            child.walk_mut(f);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub strikethrough: bool,
+    /// Text inside of a `<kbd>` tag.
+    pub keyboard: bool,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListKind {
+    Unordered,
+    /// Ordered list, holding the number of its first item.
+    Ordered(u64),
+}
+
+impl From<Option<u64>> for ListKind {
+    fn from(value: Option<u64>) -> Self {
+        match value {
+            Some(start) => ListKind::Ordered(start),
+            None => ListKind::Unordered,
+        }
+    }
+}
+
+/// Inline content of a single table cell.
+pub type TableCell = Vec<Token>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ColumnAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl From<Alignment> for ColumnAlignment {
+    fn from(value: Alignment) -> Self {
+        match value {
+            Alignment::None | Alignment::Left => ColumnAlignment::Left,
+            Alignment::Center => ColumnAlignment::Center,
+            Alignment::Right => ColumnAlignment::Right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdmonitionKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl From<BlockQuoteKind> for AdmonitionKind {
+    fn from(value: BlockQuoteKind) -> Self {
+        match value {
+            BlockQuoteKind::Note => AdmonitionKind::Note,
+            BlockQuoteKind::Tip => AdmonitionKind::Tip,
+            BlockQuoteKind::Important => AdmonitionKind::Important,
+            BlockQuoteKind::Warning => AdmonitionKind::Warning,
+            BlockQuoteKind::Caution => AdmonitionKind::Caution,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct InnerHeadingLevel(u8);
 
 impl From<HeadingLevel> for InnerHeadingLevel {
@@ -60,19 +226,12 @@ impl From<InnerHeadingLevel> for u8 {
 }
 
 fn take_heading<'a>(iter: &mut impl Iterator<Item = Event<'a>>, level: HeadingLevel) -> Token {
-    let mut content = String::new();
-
-    loop {
-        match iter.next() {
-            Some(Event::Text(t)) => content.push_str(&t),
-            Some(Event::End(TagEnd::Heading(_))) => break,
-            _ => {}
-        }
-    }
+    let parts = take_inlines(iter, TagEnd::Heading(level), TextStyle::default());
 
     Token::Heading {
         level: level.into(),
-        content,
+        parts,
+        slug: String::new(),
     }
 }
 
@@ -82,9 +241,7 @@ fn take_image<'a>(iter: &mut impl Iterator<Item = Event<'a>>, event: Event<'a>)
     };
     let mut link_text = String::new();
 
-    loop {
-        let Some(next) = iter.next() else { break };
-
+    for next in iter.by_ref() {
         match next {
             Event::Text(text) => link_text.push_str(&text),
             Event::End(TagEnd::Image) => break,
@@ -98,148 +255,237 @@ fn take_image<'a>(iter: &mut impl Iterator<Item = Event<'a>>, event: Event<'a>)
     }
 }
 
-fn take_table_headings<'a>(iter: &mut impl Iterator<Item = Event<'a>>) -> Vec<String> {
-    assert!(matches!(iter.next(), Some(Event::Start(Tag::TableHead))));
+fn take_table_cells<'a>(iter: &mut impl Iterator<Item = Event<'a>>, end: TagEnd) -> Vec<TableCell> {
+    let mut cells = vec![];
 
-    let mut headings = vec![];
-
-    loop {
-        match iter.next() {
-            Some(Event::Text(text)) => headings.push(text.to_string()),
-            Some(Event::End(TagEnd::TableHead)) => break,
+    while let Some(event) = iter.next() {
+        match event {
+            Event::Start(Tag::TableCell) => {
+                cells.push(take_inlines(iter, TagEnd::TableCell, TextStyle::default()))
+            }
+            Event::End(tag) if tag == end => break,
             _ => (),
         }
     }
 
-    headings
+    cells
 }
 
-fn take_table_row<'a>(iter: &mut impl Iterator<Item = Event<'a>>, columns: &mut [Vec<String>]) {
-    let mut idx = 0;
-    let mut column = String::new();
-    loop {
-        let next = iter.next();
-        match next {
-            Some(Event::Text(text)) => {
-                column.push_str(text.as_ref());
-            }
-            Some(Event::Code(code)) => {
-                column.push_str(code.as_ref());
-            }
-            Some(Event::End(TagEnd::TableCell)) => {
-                columns[idx].push(column.clone());
-                column = String::new();
-                idx += 1;
-            }
-            Some(Event::End(TagEnd::TableRow)) => {
-                break;
-            }
+/// Takes a table, padding ragged rows with empty cells so every row has the same width.
+fn take_table<'a>(iter: &mut impl Iterator<Item = Event<'a>>, alignments: Vec<Alignment>) -> Token {
+    let mut header = vec![];
+    let mut rows = vec![];
+
+    while let Some(event) = iter.next() {
+        match event {
+            Event::Start(Tag::TableHead) => header = take_table_cells(iter, TagEnd::TableHead),
+            Event::Start(Tag::TableRow) => rows.push(take_table_cells(iter, TagEnd::TableRow)),
+            Event::End(TagEnd::Table) => break,
             _ => (),
         }
     }
-}
-
-fn take_table<'a>(iter: &mut impl Iterator<Item = Event<'a>>) -> Token {
-    let headings = take_table_headings(iter);
-    let mut cols = (0..headings.len())
-        .map(|_| vec![])
-        .collect::<Vec<Vec<String>>>();
 
-    loop {
-        match iter.next() {
-            Some(Event::Start(Tag::TableRow)) => take_table_row(iter, &mut cols),
-            Some(Event::End(TagEnd::Table)) => break,
-            _ => (),
-        }
+    let columns = rows
+        .iter()
+        .map(Vec::len)
+        .chain([header.len(), alignments.len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut alignments = alignments
+        .into_iter()
+        .map(ColumnAlignment::from)
+        .collect::<Vec<_>>();
+    alignments.resize(columns, ColumnAlignment::default());
+    header.resize_with(columns, Vec::new);
+    for row in &mut rows {
+        row.resize_with(columns, Vec::new);
     }
 
-    Token::Table { headings, cols }
+    Token::Table {
+        alignments,
+        header,
+        rows,
+    }
 }
 
 fn take_paragraph<'a>(iter: &mut impl Iterator<Item = Event<'a>>) -> Token {
-    let mut parts = vec![];
+    let parts = take_inlines(iter, TagEnd::Paragraph, TextStyle::default());
+    Token::Paragraph { parts }
+}
 
-    loop {
-        let Some(next) = iter.next() else { break };
+/// Collects inline content until `end`, applying `style` to every piece of text so that
+/// emphasis survives nesting, e.g. bold text inside a link or a link inside bold text.
+fn take_inlines<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    end: TagEnd,
+    mut style: TextStyle,
+) -> Vec<Token> {
+    let mut parts = vec![];
 
+    while let Some(next) = iter.next() {
         match next {
-            Event::Text(text) => parts.push(Token::Text(text.to_string())),
-            Event::Code(code) => parts.push(Token::Code(code.to_string())),
-            Event::Start(Tag::Link { .. }) => parts.push(take_link(iter, next)),
-            Event::Start(Tag::Image { .. }) => parts.push(take_image(iter, next)),
-            Event::End(TagEnd::Paragraph) => break,
-            _ => {}
+            Event::End(tag) if tag == end => break,
+            event => take_inline(iter, event, &mut style, &mut parts),
         }
     }
 
-    Token::Paragraph { parts }
+    parts
 }
 
-fn take_link<'a>(iter: &mut impl Iterator<Item = Event<'a>>, link: Event<'a>) -> Token {
-    let Event::Start(Tag::Link { dest_url, .. }) = link else {
-        unreachable!();
-    };
-
-    let mut link_text = String::new();
+/// Takes a single piece of inline content. `style` is mutable as inline HTML tags such as
+/// `<kbd>` style the text that comes after them rather than wrapping it.
+fn take_inline<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    event: Event<'a>,
+    style: &mut TextStyle,
+    parts: &mut Vec<Token>,
+) {
+    match event {
+        Event::Text(text) => parts.push(Token::Text {
+            content: text.to_string(),
+            style: *style,
+        }),
+        Event::Code(code) => parts.push(Token::Code(code.to_string())),
+        Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough) => {
+            parts.extend(take_styled(iter, event, *style))
+        }
+        Event::Start(Tag::Link { .. }) => parts.push(take_link(iter, event, *style)),
+        Event::Start(Tag::Image { .. }) => parts.push(take_image(iter, event)),
+        Event::FootnoteReference(label) => parts.push(Token::FootnoteReference {
+            label: label.to_string(),
+            index: 0,
+        }),
+        Event::SoftBreak => parts.push(Token::Text {
+            content: String::from(" "),
+            style: *style,
+        }),
+        Event::HardBreak => parts.push(Token::Text {
+            content: String::from("\n"),
+            style: *style,
+        }),
+        Event::InlineHtml(source) => parts.extend(html::lower_inline(&source, style)),
+        _ => {}
+    }
+}
 
-    loop {
-        let Some(next) = iter.next() else { break };
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link { .. } | Tag::Image { .. }
+    )
+}
 
-        match next {
-            Event::Text(text) => link_text.push_str(&text),
-            Event::End(TagEnd::Link) => break,
-            _ => {}
+fn take_styled<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    event: Event<'a>,
+    mut style: TextStyle,
+) -> Vec<Token> {
+    let end = match event {
+        Event::Start(Tag::Emphasis) => {
+            style.italic = true;
+            TagEnd::Emphasis
         }
-    }
+        Event::Start(Tag::Strong) => {
+            style.bold = true;
+            TagEnd::Strong
+        }
+        Event::Start(Tag::Strikethrough) => {
+            style.strikethrough = true;
+            TagEnd::Strikethrough
+        }
+        _ => unreachable!(),
+    };
+
+    take_inlines(iter, end, style)
+}
+
+fn take_link<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    link: Event<'a>,
+    style: TextStyle,
+) -> Token {
+    let Event::Start(Tag::Link { dest_url, .. }) = link else {
+        unreachable!();
+    };
 
     Token::Link {
         uri: dest_url.to_string(),
-        label: link_text,
+        parts: take_inlines(iter, TagEnd::Link, style),
+        index: 0,
     }
 }
 
-fn take_list<'a>(iter: &mut impl Iterator<Item = Event<'a>>) -> Token {
+fn take_list<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    start: Option<u64>,
+    highlighter: &Highlighter,
+) -> Token {
     let mut items = vec![];
 
     while let Some(Event::Start(Tag::Item)) = iter.next() {
-        items.push(take_list_items(iter));
+        items.push(take_list_items(iter, highlighter));
     }
 
-    Token::List { items }
+    Token::List {
+        kind: start.into(),
+        items,
+    }
 }
 
-fn take_list_items<'a>(iter: &mut impl Iterator<Item = Event<'a>>) -> Token {
+/// Tight list items hold their inline content directly while loose ones wrap it in paragraphs,
+/// so inline runs are grouped into paragraphs to give every item a uniform list of blocks.
+fn take_list_items<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    highlighter: &Highlighter,
+) -> Token {
+    let mut children = vec![];
     let mut parts = vec![];
+    let mut style = TextStyle::default();
+    let mut checked = None;
 
-    loop {
-        let Some(next) = iter.next() else { break };
-
+    while let Some(next) = iter.next() {
         match next {
-            Event::Text(text) => parts.push(Token::Text(text.to_string())),
-            Event::Code(code) => parts.push(Token::Code(code.to_string())),
-            Event::Start(Tag::Link { .. }) => parts.push(take_link(iter, next)),
-            Event::Start(Tag::List(_)) => parts.push(take_list(iter)),
+            Event::TaskListMarker(done) => checked = Some(done),
             Event::End(TagEnd::Item) => break,
-            _ => (),
+            Event::Start(ref tag) if !is_inline(tag) => {
+                end_paragraph(&mut parts, &mut children);
+                children.extend(take_block(iter, next, highlighter));
+            }
+            Event::Rule => {
+                end_paragraph(&mut parts, &mut children);
+                children.extend(take_block(iter, next, highlighter));
+            }
+            event => take_inline(iter, event, &mut style, &mut parts),
         }
     }
 
-    Token::ListItem { parts }
+    end_paragraph(&mut parts, &mut children);
+
+    Token::ListItem { children, checked }
+}
+
+/// Groups the inline content taken so far into a paragraph of its own, if there is any.
+fn end_paragraph(parts: &mut Vec<Token>, children: &mut Vec<Token>) {
+    if !parts.is_empty() {
+        let parts = std::mem::take(parts);
+        children.push(Token::Paragraph { parts });
+    }
 }
 
-fn take_code_block<'a>(iter: &mut impl Iterator<Item = Event<'a>>, kind: CodeBlockKind) -> Token {
+fn take_code_block<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    kind: CodeBlockKind,
+    highlighter: &Highlighter,
+) -> Token {
     let mut content = String::new();
 
-    let language = match kind {
-        CodeBlockKind::Indented => String::new(),
-        CodeBlockKind::Fenced(lang) => lang.to_string(),
+    let (language, attributes) = match kind {
+        CodeBlockKind::Indented => (String::new(), vec![]),
+        CodeBlockKind::Fenced(info) => syntax::split_info_string(&info),
     };
 
-    loop {
-        let Some(next) = iter.next() else {
-            break;
-        };
-
+    for next in iter.by_ref() {
         match next {
             Event::Text(text) => content.push_str(&text),
             Event::End(TagEnd::CodeBlock) => break,
@@ -247,30 +493,296 @@ fn take_code_block<'a>(iter: &mut impl Iterator<Item = Event<'a>>, kind: CodeBlo
         }
     }
 
-    let content = syntax::highlight_code(content, &language);
+    let content = highlighter.highlight(&content, &language);
 
-    Token::CodeBlock { content, language }
+    Token::CodeBlock {
+        content,
+        language,
+        attributes,
+    }
 }
 
-pub fn parse(input: &str) -> Vec<Token> {
-    let parser = Parser::new_ext(input, Options::ENABLE_TABLES);
-    let mut iter = parser;
+fn take_block_quote<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    kind: Option<BlockQuoteKind>,
+    highlighter: &Highlighter,
+) -> Token {
+    let mut children = vec![];
+
+    while let Some(event) = iter.next() {
+        match event {
+            Event::End(TagEnd::BlockQuote(_)) => break,
+            event => children.extend(take_block(iter, event, highlighter)),
+        }
+    }
+
+    match kind {
+        Some(kind) => Token::Admonition {
+            kind: kind.into(),
+            children,
+        },
+        None => Token::BlockQuote { children },
+    }
+}
+
+fn take_html_source<'a>(iter: &mut impl Iterator<Item = Event<'a>>) -> String {
+    let mut source = String::new();
+
+    for event in iter.by_ref() {
+        match event {
+            Event::Html(html) => source.push_str(&html),
+            Event::End(TagEnd::HtmlBlock) => break,
+            _ => {}
+        }
+    }
+
+    source
+}
+
+fn html_paragraph(html: &str) -> Option<Token> {
+    let parts = html::lower_inline(html, &mut TextStyle::default());
+    (!parts.is_empty()).then_some(Token::Paragraph { parts })
+}
+
+/// HTML blocks are lowered into paragraphs, apart from the `<details>` sections opened in
+/// them, with whatever comes after a section closes being lowered as well.
+fn take_html_block<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    highlighter: &Highlighter,
+) -> Vec<Token> {
+    let mut source = take_html_source(iter);
     let mut tokens = vec![];
 
+    while let Some(start) = source.to_ascii_lowercase().find("<details") {
+        tokens.extend(html_paragraph(&source[..start]));
+        let (details, rest) = take_details(iter, &source[start..], highlighter);
+        tokens.push(details);
+        source = rest;
+    }
+
+    tokens.extend(html_paragraph(&source));
+    tokens
+}
+
+/// Markdown inside of a `<details>` section is parsed as regular blocks between the HTML
+/// block that opens the section and the one that closes it, so blocks are taken until a
+/// closing `</details>` shows up. The HTML after it is returned along with the section.
+fn take_details<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    source: &str,
+    highlighter: &Highlighter,
+) -> (Token, String) {
+    const CLOSING_TAG: &str = "</details>";
+
+    let (summary, rest) = html::take_summary(source);
+    let mut children = vec![];
+    let mut source = rest.to_string();
+
     loop {
-        let Some(event) = iter.next() else {
-            break;
-        };
+        // Lowercasing ASCII keeps byte offsets intact, so they can be used to slice `source`.
+        let lowercase = source.to_ascii_lowercase();
+        let open = lowercase.find("<details");
+        let close = lowercase.find(CLOSING_TAG);
+
+        match (open, close) {
+            (Some(open), close) if close.is_none_or(|close| close > open) => {
+                children.extend(html_paragraph(&source[..open]));
+                let (details, rest) = take_details(iter, &source[open..], highlighter);
+                children.push(details);
+                source = rest;
+                continue;
+            }
+            (_, Some(close)) => {
+                children.extend(html_paragraph(&source[..close]));
+                let rest = source[close + CLOSING_TAG.len()..].to_string();
+                return (Token::Details { summary, children }, rest);
+            }
+            _ => children.extend(html_paragraph(&source)),
+        }
 
+        source.clear();
+        let Some(event) = iter.next() else { break };
         match event {
-            Event::Start(Tag::Heading { level, .. }) => tokens.push(take_heading(&mut iter, level)),
-            Event::Start(Tag::List(_)) => tokens.push(take_list(&mut iter)),
-            Event::Start(Tag::Table(_)) => tokens.push(take_table(&mut iter)),
-            Event::Start(Tag::CodeBlock(kind)) => tokens.push(take_code_block(&mut iter, kind)),
-            Event::Start(Tag::Paragraph) => tokens.push(take_paragraph(&mut iter)),
+            Event::Start(Tag::HtmlBlock) => source = take_html_source(iter),
+            event => children.extend(take_block(iter, event, highlighter)),
+        }
+    }
+
+    (Token::Details { summary, children }, String::new())
+}
+
+fn take_footnote_definition<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    label: &str,
+    highlighter: &Highlighter,
+) -> Token {
+    let mut children = vec![];
+
+    while let Some(event) = iter.next() {
+        match event {
+            Event::End(TagEnd::FootnoteDefinition) => break,
+            event => children.extend(take_block(iter, event, highlighter)),
+        }
+    }
+
+    Token::FootnoteDefinition {
+        label: label.to_string(),
+        index: 0,
+        children,
+    }
+}
+
+/// Numbers footnotes by the order they are first referenced in, with definitions that are
+/// never referenced numbered after all the referenced ones.
+fn number_footnotes(tokens: &mut [Token]) {
+    let mut labels: Vec<String> = vec![];
+    let mut number = |label: &str| match labels.iter().position(|l| l == label) {
+        Some(idx) => idx + 1,
+        None => {
+            labels.push(label.to_string());
+            labels.len()
+        }
+    };
+
+    for token in tokens.iter_mut() {
+        token.walk_mut(&mut |token| {
+            if let Token::FootnoteReference { label, index } = token {
+                *index = number(label);
+            }
+        });
+    }
+
+    for token in tokens.iter_mut() {
+        token.walk_mut(&mut |token| {
+            if let Token::FootnoteDefinition { label, index, .. } = token {
+                *index = number(label);
+            }
+        });
+    }
+}
+
+fn number_links(tokens: &mut [Token]) {
+    let mut links = 0;
+
+    for token in tokens.iter_mut() {
+        token.walk_mut(&mut |token| {
+            if let Token::Link { index, .. } = token {
+                links += 1;
+                *index = links;
+            }
+        });
+    }
+}
+
+fn slug_headings(tokens: &mut [Token]) {
+    let mut slugs = Slugs::default();
+
+    for token in tokens.iter_mut() {
+        token.walk_mut(&mut |token| {
+            if let Token::Heading { parts, slug, .. } = token {
+                let text = parts.iter().map(Token::plain_text).collect::<String>();
+                *slug = slugs.slug(&text);
+            }
+        });
+    }
+}
+
+/// Takes the block started by `event`, which is usually a single token, but can be none or,
+/// for HTML, several.
+fn take_block<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    event: Event<'a>,
+    highlighter: &Highlighter,
+) -> Vec<Token> {
+    match event {
+        Event::Start(Tag::Heading { level, .. }) => vec![take_heading(iter, level)],
+        Event::Start(Tag::List(start)) => vec![take_list(iter, start, highlighter)],
+        Event::Start(Tag::Table(alignments)) => vec![take_table(iter, alignments)],
+        Event::Start(Tag::CodeBlock(kind)) => vec![take_code_block(iter, kind, highlighter)],
+        Event::Start(Tag::Paragraph) => vec![take_paragraph(iter)],
+        Event::Start(Tag::BlockQuote(kind)) => vec![take_block_quote(iter, kind, highlighter)],
+        Event::Rule => vec![Token::Rule],
+        Event::Start(Tag::HtmlBlock) => take_html_block(iter, highlighter),
+        Event::Start(Tag::FootnoteDefinition(label)) => {
+            vec![take_footnote_definition(iter, &label, highlighter)]
+        }
+        _ => vec![],
+    }
+}
+
+fn take_front_matter<'a>(
+    iter: &mut impl Iterator<Item = Event<'a>>,
+    kind: MetadataBlockKind,
+) -> Option<FrontMatter> {
+    let mut source = String::new();
+
+    for event in iter.by_ref() {
+        match event {
+            Event::Text(text) => source.push_str(&text),
+            Event::End(TagEnd::MetadataBlock(_)) => break,
             _ => {}
         }
     }
 
-    tokens
+    FrontMatter::parse(&source, kind)
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS
+        | Options::ENABLE_GFM
+}
+
+/// Reads the title of a document from its front matter, or else from its first heading,
+/// without parsing the blocks that follow it.
+pub fn parse_title(input: &str) -> Option<String> {
+    let mut iter = Parser::new_ext(input, options());
+
+    while let Some(event) = iter.next() {
+        match event {
+            Event::Start(Tag::MetadataBlock(kind)) => {
+                let title = take_front_matter(&mut iter, kind).and_then(|f| f.title);
+                if title.is_some() {
+                    return title;
+                }
+            }
+            Event::Start(Tag::Heading { level, .. }) => {
+                return Some(take_heading(&mut iter, level).plain_text());
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Parses a document, highlighting its code blocks with `highlighter`, which is best shared
+/// between documents as it is costly to load, see [`Highlighter::shared`].
+pub fn parse(input: &str, highlighter: &Highlighter) -> Document {
+    let parser = Parser::new_ext(input, options());
+    let mut iter = parser;
+    let mut tokens = vec![];
+    let mut front_matter = None;
+
+    while let Some(event) = iter.next() {
+        match event {
+            Event::Start(Tag::MetadataBlock(kind)) => {
+                front_matter = take_front_matter(&mut iter, kind)
+            }
+            event => tokens.extend(take_block(&mut iter, event, highlighter)),
+        }
+    }
+
+    number_footnotes(&mut tokens);
+    number_links(&mut tokens);
+    slug_headings(&mut tokens);
+
+    Document {
+        front_matter,
+        tokens,
+    }
 }
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+/// Turns the text of a heading into the anchor GitHub generates for it: lowercased, with
+/// punctuation removed and spaces replaced by hyphens.
+pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Hands out unique slugs, suffixing repeated ones with a counter the way GitHub does, so the
+/// second "Usage" heading becomes `usage-1`.
+#[derive(Debug, Default)]
+pub struct Slugs {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugs {
+    pub fn slug(&mut self, text: &str) -> String {
+        let original = slugify(text);
+        let mut slug = original.clone();
+
+        while self.seen.contains_key(&slug) {
+            let count = self.seen.entry(original.clone()).or_default();
+            *count += 1;
+            slug = format!("{original}-{count}");
+        }
+
+        self.seen.insert(slug.clone(), 0);
+        slug
+    }
+}
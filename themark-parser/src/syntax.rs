@@ -1,9 +1,15 @@
+use std::error::Error;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+use syntect::dumps::{dump_to_file, from_dump_file};
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{FontStyle, Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
+use syntect::highlighting::{FontStyle, Style, Theme, ThemeSet};
+use syntect::parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet};
 use syntect::util::LinesWithEndings;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CodeLine {
     pub parts: Vec<CodeToken>,
 }
@@ -20,7 +26,7 @@ impl From<CodeToken> for CodeLine {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CodeToken {
     pub source: String,
     pub fg: (u8, u8, u8),
@@ -47,31 +53,302 @@ impl From<String> for CodeToken {
     }
 }
 
-pub fn highlight_code(code: String, language: &str) -> Vec<CodeLine> {
-    let ps = SyntaxSet::load_defaults_newlines();
-    let theme = ThemeSet::get_theme("themes/ayu-mirage.stTheme").unwrap();
+/// Splits code into lines without highlighting it.
+pub fn plain_code(code: &str) -> Vec<CodeLine> {
+    LinesWithEndings::from(code)
+        .map(String::from)
+        .map(CodeToken::from)
+        .map(CodeLine::from)
+        .collect::<Vec<_>>()
+}
+
+/// Splits the info string of a code fence, like `rust,ignore` or `py title="main.py"`, into
+/// the language and the attributes that follow it. Attributes are separated by commas or
+/// spaces, except within quotes.
+pub fn split_info_string(info: &str) -> (String, Vec<String>) {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
 
-    let syntax = match ps.find_syntax_by_extension(language) {
-        Some(syntax) => syntax,
-        None => {
-            return code
-                .lines()
-                .map(|l| format!("{l}\n"))
-                .map(CodeToken::from)
-                .map(CodeLine::from)
-                .collect::<Vec<_>>()
+    for c in info.trim().chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            ',' | ' ' | '\t' if !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
         }
-    };
-    let mut h = HighlightLines::new(syntax, &theme);
-
-    LinesWithEndings::from(&code)
-        .map(|line| {
-            h.highlight_line(line, &ps)
-                .unwrap()
-                .into_iter()
-                .map(CodeToken::from)
-                .collect::<Vec<_>>()
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    let mut words = words.into_iter();
+    let language = words.next().unwrap_or_default();
+    (language, words.collect())
+}
+
+/// Languages code blocks are commonly labelled with that are not the name or an extension of
+/// the syntax they are highlighted with, along with the extension of that syntax.
+const ALIASES: &[(&str, &str)] = &[
+    ("shell", "sh"),
+    ("shellscript", "sh"),
+    ("shell-session", "sh"),
+    ("console", "sh"),
+    ("csharp", "cs"),
+    ("golang", "go"),
+    ("objc", "m"),
+    ("objective-c", "m"),
+    ("ts", "js"),
+    ("typescript", "js"),
+    ("jsx", "js"),
+    ("tsx", "js"),
+    ("jsonc", "json"),
+    ("json5", "json"),
+    ("elisp", "lisp"),
+    ("emacs-lisp", "lisp"),
+    ("batch", "bat"),
+    ("postgres", "sql"),
+    ("postgresql", "sql"),
+    ("psql", "sql"),
+    ("mysql", "sql"),
+    ("sqlite", "sql"),
+    ("plsql", "sql"),
+    ("text", "txt"),
+    ("plaintext", "txt"),
+    ("vue", "html"),
+];
+
+/// Finds the syntax for a code block labelled with `language`, which can be the name of the
+/// syntax, one of its extensions or one of the `ALIASES`. Unlabelled code blocks are guessed
+/// from their first line, like a shebang.
+fn find_syntax<'a>(
+    syntaxes: &'a SyntaxSet,
+    language: &str,
+    code: &str,
+) -> Option<&'a SyntaxReference> {
+    if language.is_empty() {
+        return syntaxes.find_syntax_by_first_line(code.lines().next()?);
+    }
+
+    let language = language.to_lowercase();
+    let language = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == language)
+        .map_or(language.as_str(), |(_, extension)| extension);
+    syntaxes.find_syntax_by_token(language)
+}
+
+/// Theme code is highlighted with unless another one is chosen.
+pub const DEFAULT_THEME: &str = "ayu-mirage";
+
+/// Theme code is highlighted with, along with the name it was chosen by.
+#[derive(Debug, Clone)]
+pub struct SyntaxTheme {
+    name: String,
+    theme: Theme,
+}
+
+impl SyntaxTheme {
+    /// Loads one of the themes built into the binary by its name, or else a `.tmTheme` or
+    /// `.stTheme` file from the path given as the name.
+    pub fn load(name: &str) -> Result<Self, Box<dyn Error>> {
+        let theme = match themes().themes.get(name) {
+            Some(theme) => theme.clone(),
+            None if Path::new(name).is_file() => ThemeSet::get_theme(name)?,
+            None => return Err(format!("unknown syntax theme `{name}`").into()),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            theme,
         })
-        .map(CodeLine::from)
-        .collect::<Vec<_>>()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Default for SyntaxTheme {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_THEME.to_string(),
+            theme: themes().themes[DEFAULT_THEME].clone(),
+        }
+    }
+}
+
+/// Themes built into the binary, loaded the first time they are needed. The default theme is
+/// embedded rather than read from the repository, so themark can be run from anywhere.
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = include_bytes!("../themes/ayu-mirage.stTheme");
+        let theme = ThemeSet::load_from_reader(&mut Cursor::new(theme)).unwrap();
+        themes.themes.insert(DEFAULT_THEME.to_string(), theme);
+        themes
+    })
+}
+
+/// Names of the themes built into the binary, starting with the default one.
+pub fn theme_names() -> Vec<String> {
+    std::iter::once(DEFAULT_THEME.to_string())
+        .chain(
+            themes()
+                .themes
+                .keys()
+                .filter(|name| *name != DEFAULT_THEME)
+                .cloned(),
+        )
+        .collect()
+}
+
+/// Syntaxes loaded by [`load_syntaxes`].
+pub struct UserSyntaxes {
+    pub syntaxes: SyntaxSet,
+    /// Files that could not be loaded, along with why, which are left out of `syntaxes`.
+    pub skipped: Vec<String>,
+}
+
+/// Loads syntect's syntaxes along with the `.sublime-syntax` files in `dir` and its
+/// subdirectories, or `None` when there are no such files. Building the syntaxes takes a while,
+/// so they are dumped to the `cache` file and loaded from there until the files change.
+pub fn load_syntaxes(
+    dir: &Path,
+    cache: Option<&Path>,
+) -> Result<Option<UserSyntaxes>, Box<dyn Error>> {
+    let mut files = vec![];
+    find_syntax_files(dir, &mut files)?;
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    // Anything that changes the syntaxes built, down to the version of themark building them.
+    let mut fingerprint = env!("CARGO_PKG_VERSION").to_string();
+    files.sort();
+    for file in &files {
+        let metadata = std::fs::metadata(file)?;
+        let modified = metadata.modified()?.duration_since(std::time::UNIX_EPOCH)?;
+        fingerprint.push_str(&format!(
+            "\n{} {} {}",
+            file.display(),
+            metadata.len(),
+            modified.as_nanos()
+        ));
+    }
+
+    if let Some(cache) = cache {
+        let cached = from_dump_file::<(String, Vec<String>, SyntaxSet), _>(cache);
+        if let Ok((cached, skipped, syntaxes)) = cached {
+            if cached == fingerprint {
+                return Ok(Some(UserSyntaxes { syntaxes, skipped }));
+            }
+        }
+    }
+
+    // A file that cannot be loaded is skipped, rather than keeping the rest from loading.
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    let mut skipped = vec![];
+    for file in &files {
+        let name = file.file_stem().and_then(|name| name.to_str());
+        let syntax = std::fs::read_to_string(file)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|source| Ok(SyntaxDefinition::load_from_str(&source, true, name)?));
+        match syntax {
+            Ok(syntax) => builder.add(syntax),
+            Err(error) => skipped.push(format!("{}: {error}", file.display())),
+        }
+    }
+    let syntaxes = builder.build();
+
+    // Failing to write the cache only makes loading the syntaxes slower next time.
+    if let Some(cache) = cache {
+        if let Some(parent) = cache.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = dump_to_file(&(fingerprint, &skipped, &syntaxes), cache);
+    }
+
+    Ok(Some(UserSyntaxes { syntaxes, skipped }))
+}
+
+fn find_syntax_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_syntax_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "sublime-syntax") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Syntaxes and theme code blocks are highlighted with. Loading the syntaxes takes far longer
+/// than highlighting a code block, so they are loaded once, the first time they are needed,
+/// and shared by every document parsed. The theme can be switched at any time.
+#[derive(Default)]
+pub struct Highlighter {
+    syntaxes: OnceLock<SyntaxSet>,
+    theme: RwLock<SyntaxTheme>,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highlighter shared across the whole program.
+    pub fn shared() -> &'static Highlighter {
+        static HIGHLIGHTER: OnceLock<Highlighter> = OnceLock::new();
+        HIGHLIGHTER.get_or_init(Highlighter::new)
+    }
+
+    /// Highlights code with `syntaxes` rather than syntect's own, as long as nothing has been
+    /// highlighted yet, which would have loaded those already.
+    pub fn set_syntaxes(&self, syntaxes: SyntaxSet) {
+        let _ = self.syntaxes.set(syntaxes);
+    }
+
+    pub fn set_theme(&self, theme: SyntaxTheme) {
+        *self.theme.write().unwrap() = theme;
+    }
+
+    pub fn theme_name(&self) -> String {
+        self.theme.read().unwrap().name().to_string()
+    }
+
+    pub fn highlight(&self, code: &str, language: &str) -> Vec<CodeLine> {
+        let syntaxes = self.syntaxes.get_or_init(SyntaxSet::load_defaults_newlines);
+        let Some(syntax) = find_syntax(syntaxes, language, code) else {
+            return plain_code(code);
+        };
+        let theme = self.theme.read().unwrap();
+        let mut h = HighlightLines::new(syntax, &theme.theme);
+
+        // User syntaxes can load fine and still fail on code that reaches a scope they refer
+        // to but that is not loaded, so such code is left unhighlighted.
+        let lines = LinesWithEndings::from(code)
+            .map(|line| {
+                let tokens = h.highlight_line(line, syntaxes)?;
+                Ok(tokens.into_iter().map(CodeToken::from).collect::<Vec<_>>())
+            })
+            .map(|line| line.map(CodeLine::from))
+            .collect::<Result<Vec<_>, syntect::Error>>();
+
+        lines.unwrap_or_else(|_| plain_code(code))
+    }
 }
@@ -1,3 +1,4 @@
+use crate::viewer::ViewerMessage;
 use crate::MarkdownDocument;
 use anathema::component::{Component, ComponentId, KeyCode, KeyEvent};
 use anathema::default_widgets::Overflow;
@@ -6,6 +7,7 @@ use anathema::state::{List, State, Value};
 #[derive(Debug, State)]
 pub struct MarkdownDocumentState {
     file_name: Value<String>,
+    title: Value<String>,
     path: Value<String>,
     size: Value<String>,
 }
@@ -13,6 +15,10 @@ pub struct MarkdownDocumentState {
 impl From<MarkdownDocument> for MarkdownDocumentState {
     fn from(document: MarkdownDocument) -> Self {
         Self {
+            title: document
+                .title
+                .unwrap_or_else(|| document.file_name.clone())
+                .into(),
             file_name: document.file_name.into(),
             path: document.path.to_string_lossy().to_string().into(),
             size: document.size.into(),
@@ -49,11 +55,11 @@ impl DashboardState {
 
 #[derive(Debug)]
 pub struct Dashboard {
-    viewer: ComponentId<String>,
+    viewer: ComponentId<ViewerMessage>,
 }
 
 impl Dashboard {
-    pub fn new(viewer_id: ComponentId<String>) -> Self {
+    pub fn new(viewer_id: ComponentId<ViewerMessage>) -> Self {
         Self { viewer: viewer_id }
     }
 }
@@ -71,9 +77,9 @@ impl Component for Dashboard {
     ) {
         let overflow_offset = 8;
 
-        let KeyEvent { code, .. } = key;
+        let KeyEvent { code, ctrl, .. } = key;
         let selected_idx = state.selected_idx.copy_value();
-        let max_idx = state.documents.len() - 1;
+        let max_idx = state.documents.len().saturating_sub(1);
         match code {
             KeyCode::Char('j') | KeyCode::Down => state
                 .selected_idx
@@ -89,10 +95,15 @@ impl Component for Dashboard {
                     .nth(state.selected_idx.copy_value())
                 {
                     let content = val.to_ref().path.to_ref().clone();
-                    context.emit(self.viewer, content);
+                    context.emit(self.viewer, ViewerMessage::Open(content));
                     context.publish("navigate", |state| &state.navigate_to);
                 }
             }
+            KeyCode::Char('q') => context.stop_runtime(),
+            KeyCode::Esc => context.publish("escape", |state| &state.navigate_to),
+            KeyCode::Backspace => context.publish("back", |state| &state.navigate_to),
+            KeyCode::Char('o') if ctrl => context.publish("back", |state| &state.navigate_to),
+            KeyCode::Tab => context.publish("forward", |state| &state.navigate_to),
             _ => {}
         }
 
@@ -1,15 +1,35 @@
 use anathema::state::{CommonVal, Hex, List, Path, State, Subscriber, Value, ValueRef};
+use regex::Regex;
 
 use themark_parser::{
-    syntax::{CodeLine, CodeToken},
-    Token,
+    syntax::{CodeLine, CodeToken, Highlighter},
+    AdmonitionKind, ColumnAlignment, ListKind, TableCell, TextStyle, Token,
 };
 
+use crate::search::split_matches;
+
+/// Bullets used by unordered lists, cycled through as lists get nested deeper.
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+
+/// Widest a table column gets before its cells are wrapped onto more lines.
+const TABLE_COLUMN_WIDTH: usize = 40;
+
+/// Text is rendered in pieces, so the matches of a search can be highlighted within it.
+#[derive(State, Debug)]
+pub struct InnerTextPiece {
+    text: Value<String>,
+    hit: Value<bool>,
+    current: Value<bool>,
+    /// Number of the match this piece is, counting from 1, or 0 for text in between matches.
+    index: Value<usize>,
+}
+
 #[derive(State, Debug)]
 pub struct InnerCodeToken {
     source: Value<String>,
     fg: Value<Hex>,
     bold: Value<bool>,
+    pieces: Value<List<InnerTextPiece>>,
 }
 
 #[derive(State, Debug)]
@@ -17,44 +37,142 @@ pub struct InnerCodeLine {
     parts: Value<List<InnerCodeToken>>,
 }
 
+#[derive(State, Debug)]
+pub struct InnerQuoteBlock {
+    gutter: Value<String>,
+    /// How many `<details>` sections the block is nested in within the quote or section, and
+    /// whether one of those is collapsed.
+    depth: Value<usize>,
+    hidden: Value<bool>,
+    token: Value<InnerToken>,
+}
+
+#[derive(State, Debug)]
+pub struct InnerListRow {
+    indent: Value<usize>,
+    bullet: Value<String>,
+    /// Gutter of the quotes and sections the row is nested in within its item.
+    gutter: Value<String>,
+    /// Like the blocks of quotes, how many `<details>` sections the row is nested in and
+    /// whether one of those is collapsed.
+    depth: Value<usize>,
+    hidden: Value<bool>,
+    checked: Value<bool>,
+    token: Value<InnerToken>,
+}
+
+#[derive(State, Debug)]
+pub struct InnerTableCell {
+    /// Padding before and after the content, aligning it within its column.
+    left: Value<String>,
+    right: Value<String>,
+    parts: Value<List<InnerToken>>,
+}
+
+#[derive(State, Debug)]
+pub struct InnerTableLine {
+    cells: Value<List<InnerTableCell>>,
+}
+
+/// A row of a table, spanning as many lines as its tallest wrapped cell.
+#[derive(State, Debug)]
+pub struct InnerTableRow {
+    lines: Value<List<InnerTableLine>>,
+}
+
+#[derive(State, Debug)]
+pub struct InnerTableLayout {
+    header: Value<List<InnerTableLine>>,
+    rows: Value<List<InnerTableRow>>,
+    top: Value<String>,
+    separator: Value<String>,
+    bottom: Value<String>,
+}
+
 #[derive(Debug)]
 pub enum InnerToken {
-    Text(Value<String>),
+    Text {
+        contents: Value<String>,
+        bold: Value<bool>,
+        italic: Value<bool>,
+        strikethrough: Value<bool>,
+        keyboard: Value<bool>,
+        pieces: Value<List<InnerTextPiece>>,
+    },
     Paragraph {
         parts: Value<List<InnerToken>>,
     },
+    /// Table laid out both with its cells wrapped and at full width, so switching between the
+    /// two does not need the document to be parsed again.
     Table {
-        headings: Value<List<String>>,
-        cols: Value<List<Value<List<String>>>>,
+        id: Value<String>,
+        focused: Value<bool>,
+        wrap: Value<bool>,
+        wrapped: Value<InnerTableLayout>,
+        full: Value<InnerTableLayout>,
+    },
+    Code {
+        contents: Value<String>,
+        pieces: Value<List<InnerTextPiece>>,
     },
-    Code(Value<String>),
     CodeBlock {
         content: Value<List<InnerCodeLine>>,
         language: Value<String>,
     },
     Heading {
         level: Value<u8>,
-        content: Value<String>,
+        parts: Value<List<InnerToken>>,
+        anchor: Value<String>,
     },
     List {
-        items: Value<List<InnerToken>>,
+        items: Value<List<InnerListRow>>,
+        total_tasks: Value<usize>,
+        done_tasks: Value<usize>,
     },
     Link {
         uri: Value<String>,
-        label: Value<String>,
+        parts: Value<List<InnerToken>>,
+        index: Value<usize>,
+        selected: Value<bool>,
     },
     Image {
         uri: Value<String>,
         text: Value<String>,
     },
-    ListItem {
-        parts: Value<List<InnerToken>>,
+    BlockQuote {
+        children: Value<List<InnerQuoteBlock>>,
+    },
+    Admonition {
+        title: Value<String>,
+        icon: Value<String>,
+        color: Value<String>,
+        children: Value<List<InnerQuoteBlock>>,
+    },
+    FootnoteReference {
+        index: Value<usize>,
+        marker: Value<String>,
+    },
+    FootnoteDefinition {
+        marker: Value<String>,
+        anchor: Value<String>,
+        children: Value<List<InnerToken>>,
+    },
+    Rule,
+    Html(Value<String>),
+    /// A `<details>` section. Sections nested in quotes or other sections only show their
+    /// summary, their blocks are rows of whatever they are nested in.
+    Details {
+        anchor: Value<String>,
+        summary: Value<String>,
+        expanded: Value<bool>,
+        children: Value<List<InnerQuoteBlock>>,
     },
 }
 
 impl From<CodeToken> for InnerCodeToken {
     fn from(value: CodeToken) -> Self {
         Self {
+            pieces: text_pieces(&value.source),
             source: Value::from(value.source),
             fg: Value::from(Hex::from(value.fg)),
             bold: Value::from(value.bold),
@@ -70,40 +188,825 @@ impl From<CodeLine> for InnerCodeLine {
     }
 }
 
+fn text_pieces(text: &str) -> Value<List<InnerTextPiece>> {
+    List::from_iter([InnerTextPiece {
+        text: Value::new(text.to_string()),
+        hit: Value::new(false),
+        current: Value::new(false),
+        index: Value::new(0),
+    }])
+}
+
+/// Splits `text` into new pieces around the matches of `regex`, numbering the matches on from
+/// `matches`. Without a regex the text goes back to being a single piece.
+fn highlight_pieces(
+    pieces: &mut Value<List<InnerTextPiece>>,
+    text: &str,
+    regex: Option<&Regex>,
+    matches: &mut usize,
+) {
+    let split = match regex {
+        Some(regex) => split_matches(text, regex),
+        None => vec![(text, false)],
+    };
+
+    while pieces.len() > 0 {
+        pieces.pop_back();
+    }
+
+    for (text, hit) in split {
+        let index = match hit {
+            true => {
+                *matches += 1;
+                *matches
+            }
+            false => 0,
+        };
+        pieces.push_back(InnerTextPiece {
+            text: Value::new(text.to_string()),
+            hit: Value::new(hit),
+            current: Value::new(false),
+            index: Value::new(index),
+        });
+    }
+}
+
+fn mark_current_piece(pieces: &mut Value<List<InnerTextPiece>>, current: usize) {
+    pieces.for_each(|piece| {
+        let is_current = piece.hit.copy_value() && piece.index.copy_value() == current;
+        piece.current.set(is_current);
+    });
+}
+
+pub fn block_anchor(index: usize) -> String {
+    format!("block-{index}")
+}
+
+pub fn heading_anchor(slug: &str) -> String {
+    format!("heading-{slug}")
+}
+
+pub fn table_anchor(index: usize) -> String {
+    format!("table-{index}")
+}
+
+pub fn details_anchor(index: usize) -> String {
+    format!("details-{index}")
+}
+
+pub fn footnote_anchor(index: usize) -> String {
+    format!("footnote-{index}")
+}
+
+fn superscript(number: usize) -> String {
+    const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    number
+        .to_string()
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| DIGITS[d as usize])
+        .collect()
+}
+
+/// Title, icon and the name of the theme colour of the box each kind of admonition is drawn
+/// in, which the templates look up in `COLORS`.
+fn admonition_style(kind: AdmonitionKind) -> (&'static str, &'static str, &'static str) {
+    match kind {
+        AdmonitionKind::Note => ("Note", "ⓘ", "secondary"),
+        AdmonitionKind::Tip => ("Tip", "✱", "success"),
+        AdmonitionKind::Important => ("Important", "✦", "hover"),
+        AdmonitionKind::Warning => ("Warning", "⚠", "warning"),
+        AdmonitionKind::Caution => ("Caution", "✖", "error"),
+    }
+}
+
+/// Nested quotes and `<details>` sections cannot be rendered recursively by the templates, so
+/// every block in them is lifted into a flat list that carries the gutter for its nesting.
+/// A nested section gets a row for its summary, followed by the rows of its blocks.
+fn flatten_quote(
+    children: Vec<Token>,
+    gutter: &str,
+    depth: usize,
+    blocks: &mut Vec<InnerQuoteBlock>,
+) {
+    for child in children {
+        let token = match child {
+            Token::BlockQuote { children } => {
+                flatten_quote(children, &format!("{gutter}▎ "), depth, blocks);
+                continue;
+            }
+            Token::Details { summary, children } => {
+                blocks.push(quote_block(gutter, depth, inner_details(summary, vec![])));
+                flatten_quote(children, &format!("{gutter}  "), depth + 1, blocks);
+                continue;
+            }
+            token => token.into(),
+        };
+        blocks.push(quote_block(gutter, depth, token));
+    }
+}
+
+fn quote_block(gutter: &str, depth: usize, token: InnerToken) -> InnerQuoteBlock {
+    InnerQuoteBlock {
+        gutter: Value::from(gutter.to_string()),
+        depth: Value::new(depth),
+        hidden: Value::new(false),
+        token: Value::new(token),
+    }
+}
+
+fn inner_quote(children: Vec<Token>, gutter: &str) -> Value<List<InnerQuoteBlock>> {
+    let mut blocks = vec![];
+    flatten_quote(children, gutter, 0, &mut blocks);
+    let mut blocks = List::from_iter(blocks);
+    hide_collapsed(&mut blocks);
+    blocks
+}
+
+fn inner_details(summary: String, children: Vec<Token>) -> InnerToken {
+    InnerToken::Details {
+        anchor: Value::default(),
+        summary: Value::from(summary),
+        expanded: Value::new(false),
+        children: inner_quote(children, ""),
+    }
+}
+
+/// Rows that quotes, sections and lists are flattened into, which can have `<details>`
+/// sections nested in them.
+trait SectionRow {
+    fn depth(&self) -> usize;
+    fn token(&self) -> &Value<InnerToken>;
+    fn set_hidden(&mut self, hidden: bool);
+}
+
+impl SectionRow for InnerQuoteBlock {
+    fn depth(&self) -> usize {
+        self.depth.copy_value()
+    }
+
+    fn token(&self) -> &Value<InnerToken> {
+        &self.token
+    }
+
+    fn set_hidden(&mut self, hidden: bool) {
+        self.hidden.set(hidden);
+    }
+}
+
+impl SectionRow for InnerListRow {
+    fn depth(&self) -> usize {
+        self.depth.copy_value()
+    }
+
+    fn token(&self) -> &Value<InnerToken> {
+        &self.token
+    }
+
+    fn set_hidden(&mut self, hidden: bool) {
+        self.hidden.set(hidden);
+    }
+}
+
+/// Hides the rows of every collapsed section nested in a quote, section or list, which are the
+/// rows after its summary that are nested deeper than it.
+fn hide_collapsed<T: State + SectionRow>(rows: &mut Value<List<T>>) {
+    let mut collapsed: Option<usize> = None;
+    rows.for_each(|row| {
+        let depth = row.depth();
+        if collapsed.is_some_and(|collapsed| depth <= collapsed) {
+            collapsed = None;
+        }
+        row.set_hidden(collapsed.is_some());
+
+        let is_collapsed = matches!(
+            &*row.token().to_ref(),
+            InnerToken::Details { expanded, .. } if !expanded.copy_value()
+        );
+        if collapsed.is_none() && is_collapsed {
+            collapsed = Some(depth);
+        }
+    });
+}
+
+fn list_bullets(kind: ListKind, len: usize, depth: usize) -> Vec<String> {
+    match kind {
+        ListKind::Unordered => {
+            let bullet = BULLETS[depth % BULLETS.len()];
+            vec![format!("{bullet} "); len]
+        }
+        ListKind::Ordered(start) => {
+            let last = start + (len as u64).saturating_sub(1);
+            let width = last.to_string().len();
+            (start..=last).map(|n| format!("{n:>width$}. ")).collect()
+        }
+    }
+}
+
+/// Like quotes, nested lists are flattened into rows that carry their own indentation, with
+/// every block of an item getting its own row and only the first one showing the bullet.
+/// Quotes and sections in an item are flattened into rows of the item too, carrying their
+/// gutter.
+#[derive(Default)]
+struct ListRows {
+    rows: Vec<InnerListRow>,
+    total_tasks: usize,
+    done_tasks: usize,
+}
+
+/// Where the blocks of a list item are being pushed, the bullet still to be shown by its
+/// first row and what its other rows show in place of the bullet.
+struct ItemRows<'a> {
+    depth: usize,
+    /// How many sections the rows are nested in, counting the ones the list is nested in.
+    sections: usize,
+    gutter: &'a str,
+    bullet: Option<String>,
+    padding: String,
+    checked: bool,
+}
+
+impl ListRows {
+    fn push_list(&mut self, kind: ListKind, items: Vec<Token>, depth: usize, sections: usize) {
+        let bullets = list_bullets(kind, items.len(), depth);
+
+        for (item, bullet) in items.into_iter().zip(bullets) {
+            let Token::ListItem { children, checked } = item else {
+                continue;
+            };
+
+            let bullet = match checked {
+                Some(true) => String::from("☑ "),
+                Some(false) => String::from("☐ "),
+                None => bullet,
+            };
+
+            if let Some(done) = checked {
+                self.total_tasks += 1;
+                self.done_tasks += done as usize;
+            }
+
+            let mut item = ItemRows {
+                depth,
+                sections,
+                gutter: "",
+                padding: " ".repeat(bullet.chars().count()),
+                bullet: Some(bullet),
+                checked: checked.unwrap_or_default(),
+            };
+            self.push_blocks(children, &mut item);
+
+            // Items without any content of their own still show their bullet.
+            if item.bullet.is_some() {
+                self.push_row(Token::Paragraph { parts: vec![] }.into(), &mut item);
+            }
+        }
+    }
+
+    fn push_blocks(&mut self, children: Vec<Token>, item: &mut ItemRows<'_>) {
+        for child in children {
+            match child {
+                Token::List { kind, items } => {
+                    // The bullet of an item is shown before the list nested in it.
+                    if item.bullet.is_some() {
+                        self.push_row(Token::Paragraph { parts: vec![] }.into(), item);
+                    }
+                    self.push_list(kind, items, item.depth + 1, item.sections);
+                }
+                Token::BlockQuote { children } => {
+                    self.push_nested(children, "▎ ", 0, item);
+                }
+                Token::Details { summary, children } => {
+                    self.push_row(inner_details(summary, vec![]), item);
+                    self.push_nested(children, "  ", 1, item);
+                }
+                Token::Admonition { kind, children } => {
+                    let (title, icon, _) = admonition_style(kind);
+                    let title = Token::Text {
+                        content: format!("{icon} {title}"),
+                        style: TextStyle {
+                            bold: true,
+                            ..TextStyle::default()
+                        },
+                    };
+                    self.push_row(Token::Paragraph { parts: vec![title] }.into(), item);
+                    self.push_nested(children, "▎ ", 0, item);
+                }
+                token => self.push_row(token.into(), item),
+            }
+        }
+    }
+
+    /// Pushes the blocks of a quote or section in an item, extending the gutter with `gutter`
+    /// and nesting them in `sections` more sections.
+    fn push_nested(
+        &mut self,
+        children: Vec<Token>,
+        gutter: &str,
+        sections: usize,
+        item: &mut ItemRows<'_>,
+    ) {
+        let gutter = format!("{}{gutter}", item.gutter);
+        let mut nested = ItemRows {
+            depth: item.depth,
+            sections: item.sections + sections,
+            gutter: &gutter,
+            bullet: item.bullet.take(),
+            padding: item.padding.clone(),
+            checked: item.checked,
+        };
+        self.push_blocks(children, &mut nested);
+        item.bullet = nested.bullet;
+    }
+
+    fn push_row(&mut self, token: InnerToken, item: &mut ItemRows<'_>) {
+        let bullet = item.bullet.take();
+        self.rows.push(InnerListRow {
+            indent: Value::new(2 + item.depth * 2),
+            bullet: Value::new(bullet.unwrap_or_else(|| item.padding.clone())),
+            gutter: Value::new(item.gutter.to_string()),
+            depth: Value::new(item.sections),
+            hidden: Value::new(false),
+            checked: Value::new(item.checked),
+            token: Value::new(token),
+        });
+    }
+}
+
+fn inner_list(kind: ListKind, items: Vec<Token>) -> InnerToken {
+    let mut rows = ListRows::default();
+    rows.push_list(kind, items, 0, 0);
+    let mut items = List::from_iter(rows.rows);
+    hide_collapsed(&mut items);
+
+    InnerToken::List {
+        items,
+        total_tasks: Value::new(rows.total_tasks),
+        done_tasks: Value::new(rows.done_tasks),
+    }
+}
+
+/// Width of inline content as `table.aml` renders it inside of a cell.
+fn inline_width(parts: &[Token]) -> usize {
+    parts
+        .iter()
+        .map(|part| match part {
+            Token::Text { content, .. } | Token::Code(content) | Token::Html(content) => {
+                content.chars().count()
+            }
+            Token::Link { parts, .. } => inline_width(parts),
+            Token::Image { text, .. } => text.chars().count(),
+            Token::FootnoteReference { index, .. } => superscript(*index).chars().count(),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn table_border(widths: &[usize], left: &str, middle: &str, right: &str) -> String {
+    let segments = widths
+        .iter()
+        .map(|width| "─".repeat(width + 2))
+        .collect::<Vec<_>>();
+    format!("{left}{}{right}", segments.join(middle))
+}
+
+/// Breaks inline content into lines no wider than `width`, between words where possible and
+/// within words that do not fit on a line of their own.
+struct LineWrap {
+    width: usize,
+    lines: Vec<Vec<Token>>,
+    line: Vec<Token>,
+    used: usize,
+}
+
+impl LineWrap {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            lines: vec![],
+            line: vec![],
+            used: 0,
+        }
+    }
+
+    /// Pushes a single part, with `wrap` rebuilding whatever the part was nested in around
+    /// every piece it gets split into, e.g. the link around a word of its text.
+    fn push(&mut self, part: &Token, wrap: &dyn Fn(Token) -> Token) {
+        match part {
+            Token::Text { content, style } => self.push_text(content, |content| {
+                wrap(Token::Text {
+                    content,
+                    style: *style,
+                })
+            }),
+            Token::Code(code) => self.push_text(code, |code| wrap(Token::Code(code))),
+            Token::Html(html) => self.push_text(html, |html| wrap(Token::Html(html))),
+            Token::Image { uri, text } => self.push_text(text, |text| {
+                wrap(Token::Image {
+                    uri: uri.clone(),
+                    text,
+                })
+            }),
+            Token::Link { uri, parts, index } => {
+                for part in parts {
+                    self.push(part, &|part| {
+                        wrap(Token::Link {
+                            uri: uri.clone(),
+                            parts: vec![part],
+                            index: *index,
+                        })
+                    });
+                }
+            }
+            part => {
+                let width = inline_width(std::slice::from_ref(part));
+                if self.used > 0 && self.used + width > self.width {
+                    self.break_line();
+                }
+                self.used += width;
+                self.line.push(wrap(part.clone()));
+            }
+        }
+    }
+
+    fn push_text(&mut self, text: &str, make: impl Fn(String) -> Token) {
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                self.break_line();
+            }
+
+            for mut word in line.split_inclusive(' ') {
+                if self.used > 0 && self.used + word.trim_end().chars().count() > self.width {
+                    self.break_line();
+                }
+
+                if self.used == 0 {
+                    word = word.trim_start();
+                }
+
+                while self.used + word.trim_end().chars().count() > self.width {
+                    let split = word
+                        .char_indices()
+                        .nth(self.width - self.used)
+                        .map_or(word.len(), |(idx, _)| idx);
+                    self.line.push(make(word[..split].to_string()));
+                    self.break_line();
+                    word = &word[split..];
+                }
+
+                if !word.is_empty() {
+                    self.used += word.chars().count();
+                    self.line.push(make(word.to_string()));
+                }
+            }
+        }
+    }
+
+    fn break_line(&mut self) {
+        let mut line = std::mem::take(&mut self.line);
+        if let Some(last) = line.last_mut() {
+            trim_end(last);
+        }
+        self.lines.push(line);
+        self.used = 0;
+    }
+
+    fn finish(mut self) -> Vec<Vec<Token>> {
+        self.break_line();
+        self.lines
+    }
+}
+
+fn trim_end(part: &mut Token) {
+    match part {
+        Token::Text { content, .. }
+        | Token::Code(content)
+        | Token::Html(content)
+        | Token::Image { text: content, .. } => content.truncate(content.trim_end().len()),
+        Token::Link { parts, .. } => {
+            if let Some(last) = parts.last_mut() {
+                trim_end(last);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Lines of every cell in a row, after wrapping.
+type WrappedRow = Vec<Vec<Vec<Token>>>;
+
+fn wrap_row(cells: &[TableCell], width: usize) -> WrappedRow {
+    cells
+        .iter()
+        .map(|cell| {
+            let mut wrap = LineWrap::new(width);
+            for part in cell {
+                wrap.push(part, &|part| part);
+            }
+            wrap.finish()
+        })
+        .collect()
+}
+
+fn inner_table_lines(
+    row: WrappedRow,
+    widths: &[usize],
+    alignments: &[ColumnAlignment],
+) -> Value<List<InnerTableLine>> {
+    let height = row.iter().map(Vec::len).max().unwrap_or_default();
+    let mut cells = row.into_iter().map(Vec::into_iter).collect::<Vec<_>>();
+
+    let lines = (0..height).map(|_| {
+        let cells = cells
+            .iter_mut()
+            .zip(widths.iter().zip(alignments))
+            .map(|(lines, (width, alignment))| {
+                let parts = lines.next().unwrap_or_default();
+                let space = width - inline_width(&parts);
+                let left = match alignment {
+                    ColumnAlignment::Left => 0,
+                    ColumnAlignment::Center => space / 2,
+                    ColumnAlignment::Right => space,
+                };
+
+                InnerTableCell {
+                    left: Value::new(" ".repeat(left)),
+                    right: Value::new(" ".repeat(space - left)),
+                    parts: List::from_iter(parts.into_iter().map(InnerToken::from)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        InnerTableLine {
+            cells: List::from_iter(cells),
+        }
+    });
+
+    List::from_iter(lines)
+}
+
+/// Columns are as wide as their widest line, so every cell is padded up to that width
+/// according to the alignment of its column.
+fn table_layout(
+    alignments: &[ColumnAlignment],
+    header: &[TableCell],
+    rows: &[Vec<TableCell>],
+    max_width: usize,
+) -> InnerTableLayout {
+    let header = wrap_row(header, max_width);
+    let rows = rows
+        .iter()
+        .map(|row| wrap_row(row, max_width))
+        .collect::<Vec<_>>();
+
+    let widths = (0..alignments.len())
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .flat_map(|row| &row[column])
+                .map(|line| inline_width(line))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    InnerTableLayout {
+        header: inner_table_lines(header, &widths, alignments),
+        rows: List::from_iter(rows.into_iter().map(|row| InnerTableRow {
+            lines: inner_table_lines(row, &widths, alignments),
+        })),
+        top: Value::new(table_border(&widths, "┌", "┬", "┐")),
+        separator: Value::new(table_border(&widths, "├", "┼", "┤")),
+        bottom: Value::new(table_border(&widths, "└", "┴", "┘")),
+    }
+}
+
+fn inner_table(
+    alignments: Vec<ColumnAlignment>,
+    header: Vec<TableCell>,
+    rows: Vec<Vec<TableCell>>,
+) -> InnerToken {
+    InnerToken::Table {
+        id: Value::default(),
+        focused: Value::new(false),
+        wrap: Value::new(true),
+        wrapped: Value::new(table_layout(
+            &alignments,
+            &header,
+            &rows,
+            TABLE_COLUMN_WIDTH,
+        )),
+        full: Value::new(table_layout(&alignments, &header, &rows, usize::MAX)),
+    }
+}
+
+impl InnerToken {
+    /// Calls `f` with this token and then with every token nested inside of it, including
+    /// the cells of both layouts of a table.
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut InnerToken)) {
+        self.walk(true, f);
+    }
+
+    /// Like [`InnerToken::walk_mut`], but only walks what is on screen: the layout of a table
+    /// that is shown, so text in tables is not visited twice, and no collapsed details.
+    pub fn walk_shown_mut(&mut self, f: &mut impl FnMut(&mut InnerToken)) {
+        self.walk(false, f);
+    }
+
+    fn walk(&mut self, both_layouts: bool, f: &mut impl FnMut(&mut InnerToken)) {
+        f(self);
+
+        match self {
+            InnerToken::Paragraph { parts }
+            | InnerToken::Heading { parts, .. }
+            | InnerToken::Link { parts, .. } => parts.for_each(|part| part.walk(both_layouts, f)),
+            InnerToken::FootnoteDefinition { children, .. } => {
+                children.for_each(|child| child.walk(both_layouts, f))
+            }
+            InnerToken::List { items, .. } => items.for_each(|row| {
+                if both_layouts || !row.hidden.copy_value() {
+                    row.token.to_mut().walk(both_layouts, f)
+                }
+            }),
+            InnerToken::Details { expanded, .. } if !both_layouts && !expanded.copy_value() => {}
+            InnerToken::BlockQuote { children }
+            | InnerToken::Admonition { children, .. }
+            | InnerToken::Details { children, .. } => children.for_each(|block| {
+                if both_layouts || !block.hidden.copy_value() {
+                    block.token.to_mut().walk(both_layouts, f)
+                }
+            }),
+            InnerToken::Table {
+                wrap,
+                wrapped,
+                full,
+                ..
+            } => {
+                let layouts = match (both_layouts, wrap.copy_value()) {
+                    (true, _) => vec![wrapped, full],
+                    (false, true) => vec![wrapped],
+                    (false, false) => vec![full],
+                };
+                for layout in layouts {
+                    let mut layout = layout.to_mut();
+                    let mut walk_line = |line: &mut InnerTableLine| {
+                        line.cells
+                            .for_each(|cell| cell.parts.for_each(|part| part.walk(both_layouts, f)))
+                    };
+                    layout.header.for_each(&mut walk_line);
+                    layout
+                        .rows
+                        .for_each(|row| row.lines.for_each(&mut walk_line));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Shows or hides the rows of the sections nested in this token, after they were expanded
+    /// or collapsed.
+    pub fn hide_collapsed_rows(&mut self) {
+        match self {
+            InnerToken::BlockQuote { children }
+            | InnerToken::Admonition { children, .. }
+            | InnerToken::Details { children, .. } => hide_collapsed(children),
+            InnerToken::List { items, .. } => hide_collapsed(items),
+            _ => {}
+        }
+    }
+
+    /// Highlights the matches of `regex` in the text of this token, not counting the tokens
+    /// nested in it, numbering them on from `matches`. Without a regex the highlights are
+    /// cleared.
+    pub fn highlight(&mut self, regex: Option<&Regex>, matches: &mut usize) {
+        match self {
+            InnerToken::Text {
+                contents, pieces, ..
+            }
+            | InnerToken::Code { contents, pieces } => {
+                let contents = contents.to_ref().clone();
+                highlight_pieces(pieces, &contents, regex, matches);
+            }
+            InnerToken::CodeBlock { content, .. } => content.for_each(|line| {
+                line.parts.for_each(|part| {
+                    let source = part.source.to_ref().clone();
+                    highlight_pieces(&mut part.pieces, &source, regex, matches);
+                })
+            }),
+            _ => {}
+        }
+    }
+
+    /// Marks the match numbered `current` as the current one in the text of this token,
+    /// unmarking any other match.
+    pub fn mark_current_match(&mut self, current: usize) {
+        match self {
+            InnerToken::Text { pieces, .. } | InnerToken::Code { pieces, .. } => {
+                mark_current_piece(pieces, current)
+            }
+            InnerToken::CodeBlock { content, .. } => content.for_each(|line| {
+                line.parts
+                    .for_each(|part| mark_current_piece(&mut part.pieces, current))
+            }),
+            _ => {}
+        }
+    }
+
+    /// Highlights a code block again, after the theme of `highlighter` changed. Any matches of
+    /// a search in it are cleared.
+    pub fn highlight_code(&mut self, highlighter: &Highlighter) {
+        let InnerToken::CodeBlock { content, language } = self else {
+            return;
+        };
+
+        let mut code = String::new();
+        content.for_each(|line| {
+            line.parts
+                .for_each(|part| code.push_str(&part.source.to_ref()))
+        });
+        let lines = highlighter.highlight(&code, &language.to_ref());
+
+        while content.len() > 0 {
+            content.pop_back();
+        }
+        for line in lines {
+            content.push_back(InnerCodeLine::from(line));
+        }
+    }
+}
+
 impl From<Token> for InnerToken {
     fn from(value: Token) -> Self {
         match value {
             Token::Paragraph { parts } => InnerToken::Paragraph {
                 parts: List::from_iter(parts.into_iter().map(InnerToken::from)),
             },
-            Token::Text(t) => InnerToken::Text(Value::from(t)),
-            Token::Heading { level, content } => InnerToken::Heading {
-                level: Value::from(u8::from(level)),
-                content: Value::from(content),
+            Token::Text { content, style } => InnerToken::Text {
+                pieces: text_pieces(&content),
+                contents: Value::from(content),
+                bold: Value::from(style.bold),
+                italic: Value::from(style.italic),
+                strikethrough: Value::from(style.strikethrough),
+                keyboard: Value::from(style.keyboard),
             },
-            Token::List { items } => InnerToken::List {
-                items: List::from_iter(items.into_iter().map(InnerToken::from)),
+            Token::Heading { level, parts, slug } => InnerToken::Heading {
+                level: Value::from(u8::from(level)),
+                parts: List::from_iter(parts.into_iter().map(InnerToken::from)),
+                anchor: Value::new(heading_anchor(&slug)),
             },
-            Token::Code(code) => InnerToken::Code(Value::from(code)),
-            Token::Table { headings, cols } => InnerToken::Table {
-                headings: List::from_iter(headings),
-                cols: List::from_iter(cols.into_iter().map(List::from_iter)),
+            Token::List { kind, items } => inner_list(kind, items),
+            Token::Code(code) => InnerToken::Code {
+                pieces: text_pieces(&code),
+                contents: Value::from(code),
             },
-            Token::Link { uri, label } => InnerToken::Link {
+            Token::Table {
+                alignments,
+                header,
+                rows,
+            } => inner_table(alignments, header, rows),
+            Token::Link { uri, parts, index } => InnerToken::Link {
                 uri: uri.into(),
-                label: label.into(),
+                parts: List::from_iter(parts.into_iter().map(InnerToken::from)),
+                index: Value::new(index),
+                selected: Value::new(false),
             },
             Token::Image { uri, text } => InnerToken::Image {
                 uri: uri.into(),
                 text: text.into(),
             },
-            Token::ListItem { parts } => InnerToken::ListItem {
-                parts: List::from_iter(parts.into_iter().map(InnerToken::from)),
-            },
-            Token::CodeBlock { content, language } => InnerToken::CodeBlock {
+            item @ Token::ListItem { .. } => inner_list(ListKind::Unordered, vec![item]),
+            Token::CodeBlock {
+                content, language, ..
+            } => InnerToken::CodeBlock {
                 content: List::from_iter(content.into_iter().map(Into::into)),
                 language: language.into(),
             },
+            Token::FootnoteReference { index, .. } => InnerToken::FootnoteReference {
+                index: Value::new(index),
+                marker: Value::new(superscript(index)),
+            },
+            Token::FootnoteDefinition {
+                index, children, ..
+            } => InnerToken::FootnoteDefinition {
+                marker: Value::new(superscript(index)),
+                anchor: Value::new(footnote_anchor(index)),
+                children: List::from_iter(children.into_iter().map(InnerToken::from)),
+            },
+            Token::Rule => InnerToken::Rule,
+            Token::Html(html) => InnerToken::Html(Value::from(html)),
+            Token::Details { summary, children } => inner_details(summary, children),
+            Token::Admonition { kind, children } => {
+                let (title, icon, color) = admonition_style(kind);
+                InnerToken::Admonition {
+                    title: Value::from(String::from(title)),
+                    icon: Value::from(String::from(icon)),
+                    color: Value::from(String::from(color)),
+                    children: inner_quote(children, ""),
+                }
+            }
+            Token::BlockQuote { children } => InnerToken::BlockQuote {
+                children: inner_quote(children, "▎ "),
+            },
         }
     }
 }
@@ -111,15 +1014,50 @@ impl From<Token> for InnerToken {
 impl State for InnerToken {
     fn state_get(&self, path: Path<'_>, sub: Subscriber) -> Option<ValueRef> {
         match (self, path) {
-            (InnerToken::Text(v), _) => Some(v.value_ref(sub)),
-            (InnerToken::Paragraph { parts }, Path::Key("parts")) => Some(parts.value_ref(sub)),
-            (InnerToken::Code(v), _) => Some(v.value_ref(sub)),
-            (InnerToken::Table { headings, .. }, Path::Key("headings")) => {
-                Some(headings.value_ref(sub))
+            (InnerToken::Text { contents, .. }, Path::Key("contents")) => {
+                Some(contents.value_ref(sub))
+            }
+            (InnerToken::Text { bold, .. }, Path::Key("bold")) => Some(bold.value_ref(sub)),
+            (InnerToken::Text { italic, .. }, Path::Key("italic")) => Some(italic.value_ref(sub)),
+            (InnerToken::Text { strikethrough, .. }, Path::Key("strikethrough")) => {
+                Some(strikethrough.value_ref(sub))
+            }
+            (InnerToken::Text { keyboard, .. }, Path::Key("keyboard")) => {
+                Some(keyboard.value_ref(sub))
+            }
+            (InnerToken::Text { pieces, .. }, Path::Key("pieces")) => Some(pieces.value_ref(sub)),
+            (InnerToken::Paragraph { parts, .. }, Path::Key("parts")) => Some(parts.value_ref(sub)),
+            (InnerToken::Code { contents, .. }, Path::Key("contents")) => {
+                Some(contents.value_ref(sub))
+            }
+            (InnerToken::Code { pieces, .. }, Path::Key("pieces")) => Some(pieces.value_ref(sub)),
+            (InnerToken::Html(v), _) => Some(v.value_ref(sub)),
+            (InnerToken::Details { anchor, .. }, Path::Key("anchor")) => {
+                Some(anchor.value_ref(sub))
+            }
+            (InnerToken::Details { summary, .. }, Path::Key("summary")) => {
+                Some(summary.value_ref(sub))
+            }
+            (InnerToken::Details { expanded, .. }, Path::Key("expanded")) => {
+                Some(expanded.value_ref(sub))
             }
-            (InnerToken::Table { cols, .. }, Path::Key("cols")) => Some(cols.value_ref(sub)),
+            (InnerToken::Details { children, .. }, Path::Key("children")) => {
+                Some(children.value_ref(sub))
+            }
+            (InnerToken::Table { id, .. }, Path::Key("id")) => Some(id.value_ref(sub)),
+            (InnerToken::Table { focused, .. }, Path::Key("focused")) => {
+                Some(focused.value_ref(sub))
+            }
+            (InnerToken::Table { wrap, .. }, Path::Key("wrap")) => Some(wrap.value_ref(sub)),
+            (InnerToken::Table { wrapped, .. }, Path::Key("wrapped")) => {
+                Some(wrapped.value_ref(sub))
+            }
+            (InnerToken::Table { full, .. }, Path::Key("full")) => Some(full.value_ref(sub)),
             (InnerToken::Link { uri, .. }, Path::Key("uri")) => Some(uri.value_ref(sub)),
-            (InnerToken::Link { label, .. }, Path::Key("label")) => Some(label.value_ref(sub)),
+            (InnerToken::Link { parts, .. }, Path::Key("parts")) => Some(parts.value_ref(sub)),
+            (InnerToken::Link { selected, .. }, Path::Key("selected")) => {
+                Some(selected.value_ref(sub))
+            }
             (InnerToken::Image { uri, .. }, Path::Key("uri")) => Some(uri.value_ref(sub)),
             (InnerToken::Image { text, .. }, Path::Key("text")) => Some(text.value_ref(sub)),
             (InnerToken::CodeBlock { language, .. }, Path::Key("language")) => {
@@ -129,18 +1067,49 @@ impl State for InnerToken {
                 Some(content.value_ref(sub))
             }
             (InnerToken::Heading { level, .. }, Path::Key("level")) => Some(level.value_ref(sub)),
-            (InnerToken::Heading { content, .. }, Path::Key("content")) => {
-                Some(content.value_ref(sub))
+            (InnerToken::Heading { parts, .. }, Path::Key("parts")) => Some(parts.value_ref(sub)),
+            (InnerToken::Heading { anchor, .. }, Path::Key("anchor")) => {
+                Some(anchor.value_ref(sub))
+            }
+            (InnerToken::List { items, .. }, Path::Key("items")) => Some(items.value_ref(sub)),
+            (InnerToken::List { total_tasks, .. }, Path::Key("total_tasks")) => {
+                Some(total_tasks.value_ref(sub))
+            }
+            (InnerToken::List { done_tasks, .. }, Path::Key("done_tasks")) => {
+                Some(done_tasks.value_ref(sub))
+            }
+            (InnerToken::BlockQuote { children }, Path::Key("children")) => {
+                Some(children.value_ref(sub))
+            }
+            (InnerToken::Admonition { title, .. }, Path::Key("title")) => {
+                Some(title.value_ref(sub))
+            }
+            (InnerToken::Admonition { icon, .. }, Path::Key("icon")) => Some(icon.value_ref(sub)),
+            (InnerToken::Admonition { color, .. }, Path::Key("color")) => {
+                Some(color.value_ref(sub))
+            }
+            (InnerToken::Admonition { children, .. }, Path::Key("children")) => {
+                Some(children.value_ref(sub))
+            }
+            (InnerToken::FootnoteReference { marker, .. }, Path::Key("marker")) => {
+                Some(marker.value_ref(sub))
+            }
+            (InnerToken::FootnoteDefinition { marker, .. }, Path::Key("marker")) => {
+                Some(marker.value_ref(sub))
+            }
+            (InnerToken::FootnoteDefinition { anchor, .. }, Path::Key("anchor")) => {
+                Some(anchor.value_ref(sub))
+            }
+            (InnerToken::FootnoteDefinition { children, .. }, Path::Key("children")) => {
+                Some(children.value_ref(sub))
             }
-            (InnerToken::List { items }, Path::Key("items")) => Some(items.value_ref(sub)),
-            (InnerToken::ListItem { parts }, Path::Key("parts")) => Some(parts.value_ref(sub)),
             _ => None,
         }
     }
 
     fn to_common(&self) -> Option<CommonVal<'_>> {
         match self {
-            InnerToken::Text(_) => Some(CommonVal::Str("text")),
+            InnerToken::Text { .. } => Some(CommonVal::Str("text")),
             InnerToken::Paragraph { .. } => Some(CommonVal::Str("paragraph")),
             InnerToken::Image { .. } => Some(CommonVal::Str("image")),
             InnerToken::Table { .. } => Some(CommonVal::Str("table")),
@@ -149,7 +1118,13 @@ impl State for InnerToken {
             InnerToken::Link { .. } => Some(CommonVal::Str("link")),
             InnerToken::Code { .. } => Some(CommonVal::Str("inline_code")),
             InnerToken::CodeBlock { .. } => Some(CommonVal::Str("code_block")),
-            InnerToken::ListItem { .. } => Some(CommonVal::Str("list_item")),
+            InnerToken::BlockQuote { .. } => Some(CommonVal::Str("blockquote")),
+            InnerToken::Admonition { .. } => Some(CommonVal::Str("admonition")),
+            InnerToken::FootnoteReference { .. } => Some(CommonVal::Str("footnote_ref")),
+            InnerToken::FootnoteDefinition { .. } => Some(CommonVal::Str("footnote")),
+            InnerToken::Rule => Some(CommonVal::Str("rule")),
+            InnerToken::Html(_) => Some(CommonVal::Str("html")),
+            InnerToken::Details { .. } => Some(CommonVal::Str("details")),
         }
     }
 }
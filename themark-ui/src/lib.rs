@@ -1,9 +1,13 @@
 mod dashboard;
 mod inner_token;
 mod router;
+mod search;
+mod templates;
+mod terminal;
+mod toc;
 mod viewer;
 
-use themark_parser::Token;
+use themark_parser::Document as MarkdownDoc;
 
 use anathema::backend::tui::TuiBackend;
 use anathema::runtime::Runtime;
@@ -15,14 +19,21 @@ use viewer::{Viewer, ViewerState};
 #[derive(Debug, PartialEq)]
 pub struct MarkdownDocument {
     file_name: String,
+    title: Option<String>,
     path: std::path::PathBuf,
     size: String,
 }
 
 impl MarkdownDocument {
-    pub fn new(file_name: String, path: std::path::PathBuf, size: String) -> Self {
+    pub fn new(
+        file_name: String,
+        title: Option<String>,
+        path: std::path::PathBuf,
+        size: String,
+    ) -> Self {
         Self {
             file_name,
+            title,
             path,
             size,
         }
@@ -33,6 +44,7 @@ impl From<std::fs::DirEntry> for MarkdownDocument {
     fn from(value: std::fs::DirEntry) -> Self {
         Self {
             file_name: value.file_name().to_string_lossy().to_string(),
+            title: themark_fs::read_title(value.path()),
             path: value.path(),
             size: themark_fs::read_file_size(value),
         }
@@ -42,20 +54,21 @@ impl From<std::fs::DirEntry> for MarkdownDocument {
 #[derive(Debug, PartialEq)]
 pub enum Entrypoint {
     Dashboard(Vec<MarkdownDocument>),
-    Viewer(Vec<Token>),
+    Viewer(std::path::PathBuf, MarkdownDoc),
 }
 
 impl std::fmt::Display for Entrypoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Entrypoint::Dashboard(_) => f.write_str("dashboard"),
-            Entrypoint::Viewer(_) => f.write_str("viewer"),
+            Entrypoint::Viewer(..) => f.write_str("viewer"),
         }
     }
 }
 
 pub fn setup(entrypoint: Entrypoint) -> Result<(), Box<dyn std::error::Error>> {
     let doc = Document::new("@router");
+    terminal::restore_on_panic();
 
     let backend = TuiBackend::builder()
         .enable_alt_screen()
@@ -67,55 +80,45 @@ pub fn setup(entrypoint: Entrypoint) -> Result<(), Box<dyn std::error::Error>> {
 
     let mut runtime = Runtime::builder(doc, backend);
 
-    let components = [
-        ("dashboard_item", "templates/components/dashboard_item.aml"),
-        ("loading_doc", "templates/components/loading_doc.aml"),
-        ("error_doc", "templates/components/error_doc.aml"),
-        ("link", "templates/components/link.aml"),
-        ("heading", "templates/components/heading.aml"),
-        ("code_block", "templates/components/code_block.aml"),
-        ("image", "templates/components/image.aml"),
-        ("table", "templates/components/table.aml"),
-        ("paragraph", "templates/components/paragraph.aml"),
-        ("list", "templates/components/list.aml"),
-    ];
-
-    for (name, path) in components {
-        runtime.register_prototype(name, path, || (), || ())?;
+    for name in templates::prototypes() {
+        runtime.register_prototype(name, templates::source(name), || (), || ())?;
     }
 
     let start_page = entrypoint.to_string();
+    let mut router = Router::builder().add_route("dashboard").add_route("viewer");
 
     match entrypoint {
         Entrypoint::Dashboard(documents) => {
-            let viewer_id = runtime.register_default::<Viewer>("viewer", "templates/viewer.aml")?;
+            let viewer_id =
+                runtime.register_default::<Viewer>("viewer", templates::source("viewer"))?;
             runtime.register_component(
                 "dashboard",
-                "templates/dashboard.aml",
+                templates::source("dashboard"),
                 Dashboard::new(viewer_id),
                 DashboardState::new(documents.into_iter().map(Into::into)),
             )?;
+            router = router.viewer(viewer_id);
         }
-        Entrypoint::Viewer(tokens) => {
+        Entrypoint::Viewer(path, document) => {
+            let path_name = path.to_string_lossy().to_string();
+            router = router.document(path_name.clone());
             let viewer_id = runtime.register_component(
                 "viewer",
-                "templates/viewer.aml",
-                Viewer,
-                ViewerState::new(tokens.into_iter().map(Into::into)),
+                templates::source("viewer"),
+                Viewer::new(path),
+                ViewerState::new(path_name, document),
             )?;
             runtime.register_component(
                 "dashboard",
-                "templates/dashboard.aml",
+                templates::source("dashboard"),
                 Dashboard::new(viewer_id),
                 DashboardState::default(),
             )?;
+            router = router.viewer(viewer_id);
         }
     }
 
-    Router::builder()
-        .add_route("dashboard")
-        .add_route("viewer")
-        .finish(start_page, &mut runtime)?;
+    router.finish(start_page, &mut runtime)?;
 
     runtime.finish()?.run();
 
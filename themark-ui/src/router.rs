@@ -1,16 +1,24 @@
 use std::fmt::Display;
 
 use anathema::backend::Backend;
-use anathema::component::Component;
+use anathema::component::{Component, ComponentId};
 use anathema::runtime::{Error, RuntimeBuilder};
-use anathema::state::{List, State, Value};
+use anathema::state::{CommonVal, List, State, Value};
 use anathema::templates::ToSourceKind;
 
+use crate::viewer::ViewerMessage;
+
 static RECEIVE_IDENT: &str = "navigate";
+/// Everything routes publish to the router, which is only passed on to it when the route is
+/// associated with it in the template.
+static PUBLISHED_IDENTS: [&str; 6] = [RECEIVE_IDENT, "open", "leave", "back", "forward", "escape"];
+static VIEWER_ROUTE: &str = "viewer";
 
 pub struct RouterBuilder {
     routes: Vec<String>,
     path: Option<String>,
+    viewer: Option<ComponentId<ViewerMessage>>,
+    document: Option<String>,
 }
 
 impl RouterBuilder {
@@ -19,13 +27,31 @@ impl RouterBuilder {
         self
     }
 
+    /// Viewer that documents are restored in when going back or forward through history.
+    pub fn viewer(mut self, viewer: ComponentId<ViewerMessage>) -> RouterBuilder {
+        self.viewer = Some(viewer);
+        self
+    }
+
+    /// Document the viewer starts out showing, if any.
+    pub fn document(mut self, path: String) -> RouterBuilder {
+        self.document = Some(path);
+        self
+    }
+
     pub fn generate_template(&self) -> String {
+        let associations = PUBLISHED_IDENTS
+            .iter()
+            .map(|ident| format!("{ident}->{ident}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
         let mut template = String::new();
         for route in &self.routes {
             let component = format!(
                 r#"
 if active_route == "{route}"
-    @{route} ({RECEIVE_IDENT}->{RECEIVE_IDENT})
+    @{route} ({associations})
             "#
             );
             template.push_str(&component);
@@ -41,10 +67,16 @@ if active_route == "{route}"
     ) -> Result<(), Error> {
         let template = self.generate_template();
 
-        let router = Router {};
+        let router = Router {
+            viewer: self.viewer,
+        };
+        let route = entrypoint.to_string();
+        let start = HistoryEntry::new(route.clone(), self.document.unwrap_or_default());
         let router_state = RouterState {
             routes: List::from_iter(self.routes),
-            active_route: entrypoint.to_string().into(),
+            active_route: route.into(),
+            history: List::from_iter([start]),
+            current: Value::new(0),
         };
 
         if self.path.is_some() {
@@ -57,13 +89,56 @@ if active_route == "{route}"
     }
 }
 
-pub struct Router;
+pub struct Router {
+    viewer: Option<ComponentId<ViewerMessage>>,
+}
 
 impl Router {
     pub fn builder() -> RouterBuilder {
         RouterBuilder {
             routes: vec![],
             path: None,
+            viewer: None,
+            document: None,
+        }
+    }
+
+    /// Moves to the entry at `index` in the history, restoring its document and scroll offset
+    /// when it is one shown by the viewer.
+    fn go_to(
+        &mut self,
+        index: usize,
+        state: &mut RouterState,
+        context: &mut anathema::prelude::Context<'_, RouterState>,
+    ) {
+        let Some((route, path, offset)) = state.entry(index) else {
+            return;
+        };
+
+        state.current.set(index);
+        if route == VIEWER_ROUTE && !path.is_empty() {
+            if let Some(viewer) = self.viewer {
+                context.emit(viewer, ViewerMessage::Restore { path, offset });
+            }
+        }
+        state.active_route.set(route);
+    }
+}
+
+#[derive(State)]
+pub struct HistoryEntry {
+    route: Value<String>,
+    /// Document shown by the viewer, empty for any other route.
+    path: Value<String>,
+    offset: Value<i32>,
+}
+
+impl HistoryEntry {
+    fn new(route: String, path: String) -> Self {
+        Self {
+            route: route.into(),
+            path: path.into(),
+            offset: Value::new(0),
         }
     }
 }
@@ -72,6 +147,41 @@ impl Router {
 pub struct RouterState {
     active_route: Value<String>,
     routes: Value<List<String>>,
+    /// Routes and documents visited so far, `current` being the one on screen.
+    history: Value<List<HistoryEntry>>,
+    current: Value<usize>,
+}
+
+impl RouterState {
+    fn entry(&self, index: usize) -> Option<(String, String, i32)> {
+        let history = self.history.to_ref();
+        let entry = history.iter().nth(index)?.to_ref();
+        let route = entry.route.to_ref().clone();
+        let path = entry.path.to_ref().clone();
+        Some((route, path, entry.offset.copy_value()))
+    }
+
+    fn with_current(&mut self, mut f: impl FnMut(&mut HistoryEntry)) {
+        let current = self.current.copy_value();
+        let mut index = 0;
+        self.history.for_each(|entry| {
+            if index == current {
+                f(entry);
+            }
+            index += 1;
+        });
+    }
+
+    /// Adds an entry after the current one, dropping whatever could be gone forward to.
+    fn push(&mut self, route: String, path: String) {
+        let current = self.current.copy_value();
+        while self.history.len() > current + 1 {
+            self.history.pop_back();
+        }
+
+        self.history.push_back(HistoryEntry::new(route, path));
+        self.current.set(current + 1);
+    }
 }
 
 impl Component for Router {
@@ -81,16 +191,45 @@ impl Component for Router {
     fn receive(
         &mut self,
         ident: &str,
-        value: anathema::state::CommonVal<'_>,
+        value: CommonVal<'_>,
         state: &mut Self::State,
         _: anathema::widgets::Elements<'_, '_>,
-        _: anathema::prelude::Context<'_, Self::State>,
+        mut context: anathema::prelude::Context<'_, Self::State>,
     ) {
-        if ident != RECEIVE_IDENT {
+        let current = state.current.copy_value();
+        let Some((current_route, current_path, _)) = state.entry(current) else {
             return;
-        }
+        };
 
-        let route = value.to_string();
-        state.active_route.set(route);
+        match ident {
+            _ if ident == RECEIVE_IDENT => {
+                let route = value.to_string();
+                if route != current_route {
+                    state.push(route.clone(), String::new());
+                }
+                state.active_route.set(route);
+            }
+            // The viewer opened a document, either one picked on the dashboard, filling in the
+            // entry navigating to the viewer added, or one linked to from another document.
+            "open" => {
+                let path = value.to_string();
+                match current_route == VIEWER_ROUTE && current_path.is_empty() {
+                    true => state.with_current(|entry| entry.path.set(path.clone())),
+                    false => state.push(VIEWER_ROUTE.to_string(), path),
+                }
+                state.active_route.set(VIEWER_ROUTE.to_string());
+            }
+            "leave" => {
+                if let CommonVal::Int(offset) = value {
+                    state.with_current(|entry| entry.offset.set(offset as i32));
+                }
+            }
+            "back" if current > 0 => self.go_to(current - 1, state, &mut context),
+            // Escape goes back as long as there is somewhere to go back to, and quits after.
+            "escape" if current > 0 => self.go_to(current - 1, state, &mut context),
+            "escape" => context.stop_runtime(),
+            "forward" => self.go_to(current + 1, state, &mut context),
+            _ => {}
+        }
     }
 }
@@ -0,0 +1,41 @@
+use regex::{Regex, RegexBuilder};
+
+/// Builds the regex searched for with `query`. Searches are smart-case, only matching case
+/// when the query has an uppercase letter in it, and queries that are not a valid regex, like
+/// one still being typed, are searched for literally.
+pub fn search_regex(query: &str) -> Option<Regex> {
+    if query.is_empty() {
+        return None;
+    }
+
+    let case_insensitive = !query.chars().any(char::is_uppercase);
+    RegexBuilder::new(query)
+        .case_insensitive(case_insensitive)
+        .build()
+        .or_else(|_| {
+            RegexBuilder::new(&regex::escape(query))
+                .case_insensitive(case_insensitive)
+                .build()
+        })
+        .ok()
+}
+
+/// Splits `text` into pieces around every match of `regex`, flagging the pieces that matched.
+/// Empty matches are skipped as there would be nothing to highlight.
+pub fn split_matches<'a>(text: &'a str, regex: &Regex) -> Vec<(&'a str, bool)> {
+    let mut pieces = vec![];
+    let mut end = 0;
+
+    for found in regex.find_iter(text).filter(|found| !found.is_empty()) {
+        if found.start() > end {
+            pieces.push((&text[end..found.start()], false));
+        }
+        pieces.push((found.as_str(), true));
+        end = found.end();
+    }
+
+    if end < text.len() || pieces.is_empty() {
+        pieces.push((&text[end..], false));
+    }
+    pieces
+}
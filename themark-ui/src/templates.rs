@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use anathema::templates::{SourceKind, ToSourceKind};
+
+/// Name a template is registered under, its path within the templates directory and its
+/// source as built into the binary.
+type Template = (&'static str, &'static str, &'static str);
+
+macro_rules! template {
+    ($name:literal, $path:literal) => {
+        ($name, $path, include_str!(concat!("../templates/", $path)))
+    };
+}
+
+const TEMPLATES: [Template; 23] = [
+    template!("dashboard", "dashboard.aml"),
+    template!("viewer", "viewer.aml"),
+    template!("dashboard_item", "components/dashboard_item.aml"),
+    template!("loading_doc", "components/loading_doc.aml"),
+    template!("error_doc", "components/error_doc.aml"),
+    template!("link", "components/link.aml"),
+    template!("heading", "components/heading.aml"),
+    template!("code_block", "components/code_block.aml"),
+    template!("image", "components/image.aml"),
+    template!("table", "components/table.aml"),
+    template!("table_layout", "components/table_layout.aml"),
+    template!("paragraph", "components/paragraph.aml"),
+    template!("text_pieces", "components/text_pieces.aml"),
+    template!("list", "components/list.aml"),
+    template!("blockquote", "components/blockquote.aml"),
+    template!("admonition", "components/admonition.aml"),
+    template!("details", "components/details.aml"),
+    template!("details_summary", "components/details_summary.aml"),
+    template!("block", "components/block.aml"),
+    template!("footnotes", "components/footnotes.aml"),
+    template!("front_matter", "components/front_matter.aml"),
+    template!("toc", "components/toc.aml"),
+    template!("status_bar", "components/status_bar.aml"),
+];
+
+/// Names of the templates that are registered as prototypes, rather than as the dashboard and
+/// viewer components.
+pub fn prototypes() -> impl Iterator<Item = &'static str> {
+    TEMPLATES
+        .iter()
+        .map(|(name, ..)| *name)
+        .filter(|name| !matches!(*name, "dashboard" | "viewer"))
+}
+
+/// Directory templates are overridden from, laid out like the `templates` directory of this
+/// crate.
+fn override_dir() -> Option<PathBuf> {
+    Some(themark_fs::config_dir()?.join("templates"))
+}
+
+/// Source of the template registered as `name`, read from the override directory when it has
+/// that template and otherwise the one built into the binary. The source is passed on as a
+/// template, as a plain string would be taken for the path of one.
+pub fn source(name: &str) -> SourceKind {
+    let Some((_, path, embedded)) = TEMPLATES.iter().find(|(n, ..)| *n == name) else {
+        panic!("no template named {name}");
+    };
+
+    override_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(path)).ok())
+        .unwrap_or_else(|| embedded.to_string())
+        .to_template()
+}
@@ -0,0 +1,24 @@
+use std::io::stdout;
+
+use crossterm::cursor::Show;
+use crossterm::event::DisableMouseCapture;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, LeaveAlternateScreen};
+
+/// Puts the terminal back the way it was before the runtime took it over. The backend only
+/// does this itself when the runtime stops normally.
+pub fn restore() {
+    // Nothing sensible can be done if restoring fails, the terminal is left as it is.
+    let _ = execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen, Show);
+    let _ = disable_raw_mode();
+}
+
+/// Makes sure a panic, e.g. from a missing syntax theme, is printed to a usable terminal
+/// instead of being lost in the alternate screen.
+pub fn restore_on_panic() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        hook(info);
+    }));
+}
@@ -0,0 +1,100 @@
+use anathema::state::{State, Value};
+
+use themark_parser::Token;
+
+use crate::inner_token::heading_anchor;
+
+/// Width of the table of contents sidebar, which entries are cut short to fit in.
+const TOC_WIDTH: usize = 32;
+
+#[derive(State, Debug)]
+pub struct TocEntry {
+    indent: Value<String>,
+    /// Text of the heading as shown in the sidebar, cut short when it does not fit.
+    label: Value<String>,
+    text: Value<String>,
+    anchor: Value<String>,
+    current: Value<bool>,
+}
+
+impl TocEntry {
+    pub fn text(&self) -> String {
+        self.text.to_ref().clone()
+    }
+
+    pub fn anchor(&self) -> String {
+        self.anchor.to_ref().clone()
+    }
+
+    pub fn set_current(&mut self, current: bool) {
+        self.current.set(current);
+    }
+
+    /// Copy of the entry for the heading picker, which marks its own current entry.
+    pub fn unmarked(&self) -> Self {
+        Self {
+            indent: Value::new(self.indent.to_ref().clone()),
+            label: Value::new(self.label.to_ref().clone()),
+            text: Value::new(self.text()),
+            anchor: Value::new(self.anchor()),
+            current: Value::new(false),
+        }
+    }
+}
+
+/// Lists every heading of the document, indented by how much deeper it is than the
+/// shallowest heading.
+pub fn toc_entries(tokens: &mut [Token]) -> Vec<TocEntry> {
+    let mut headings = vec![];
+    for token in tokens {
+        token.walk_mut(&mut |token| {
+            if let Token::Heading { level, slug, .. } = &*token {
+                headings.push((u8::from(level.clone()), token.plain_text(), slug.clone()));
+            }
+        });
+    }
+
+    let top = headings.iter().map(|(level, ..)| *level).min().unwrap_or(1);
+    headings
+        .into_iter()
+        .map(|(level, text, slug)| {
+            let indent = " ".repeat(2 * usize::from(level - top));
+            let room = TOC_WIDTH.saturating_sub(indent.len() + 2);
+            let label = match text.chars().count() > room {
+                true => format!("{}…", text.chars().take(room - 1).collect::<String>()),
+                false => text.clone(),
+            };
+
+            TocEntry {
+                indent: Value::new(indent),
+                label: Value::new(label),
+                text: Value::new(text),
+                anchor: Value::new(heading_anchor(&slug)),
+                current: Value::new(false),
+            }
+        })
+        .collect()
+}
+
+/// Scores how well `query` matches `text`, as long as its characters appear in `text` in the
+/// same order. Case is ignored, and characters that follow each other or start a word score
+/// higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = (next..text.len()).find(|&index| text[index] == c)?;
+        score += 1;
+        if found > 0 && found == next {
+            score += 4;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 2;
+        }
+        next = found + 1;
+    }
+
+    Some(score)
+}
@@ -1,63 +1,1199 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 use anathema::component::{Component, KeyCode, KeyEvent, MouseEvent, MouseState};
 use anathema::default_widgets::Overflow;
+use anathema::prelude::Context;
 use anathema::state::{List, State, Value};
+use anathema::widgets::Elements;
+use regex::Regex;
+
+use themark_fs::LinkTarget;
+use themark_parser::front_matter::FrontMatter;
+use themark_parser::syntax::{theme_names, Highlighter, SyntaxTheme};
+use themark_parser::{Document as MarkdownDoc, Token};
+
+use crate::inner_token::{
+    block_anchor, details_anchor, footnote_anchor, heading_anchor, table_anchor, InnerToken,
+};
+use crate::search::search_regex;
+use crate::toc::{fuzzy_score, toc_entries, TocEntry};
+
+/// Reading speed the reading time of a document is estimated with.
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(State, Debug)]
+pub struct FrontMatterField {
+    key: Value<String>,
+    value: Value<String>,
+}
+
+#[derive(State, Debug, Default)]
+pub struct FrontMatterState {
+    visible: Value<bool>,
+    expanded: Value<bool>,
+    title: Value<String>,
+    summary: Value<String>,
+    fields: Value<List<FrontMatterField>>,
+}
+
+impl From<Option<FrontMatter>> for FrontMatterState {
+    fn from(front_matter: Option<FrontMatter>) -> Self {
+        let Some(front_matter) = front_matter else {
+            return Self::default();
+        };
 
-use crate::inner_token::InnerToken;
+        let tags = front_matter.tags.join(", ");
+        let summary = [front_matter.author, front_matter.date, Some(tags)]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" · ");
+
+        Self {
+            visible: Value::new(true),
+            expanded: Value::new(false),
+            title: Value::new(front_matter.title.unwrap_or_else(|| "Metadata".into())),
+            summary: Value::new(summary),
+            fields: List::from_iter(front_matter.fields.into_iter().map(|(key, value)| {
+                FrontMatterField {
+                    key: key.into(),
+                    value: value.into(),
+                }
+            })),
+        }
+    }
+}
 
 #[derive(State, Debug, Default)]
 pub struct ViewerState {
+    front_matter: Value<FrontMatterState>,
     tokens: Value<List<InnerToken>>,
+    /// Ids of the top level tokens, letting the viewer find where each of them is on screen.
+    blocks: Value<List<String>>,
+    footnotes: Value<List<InnerToken>>,
     loading_document: Value<bool>,
     total_tokens: Value<usize>,
+    total_footnotes: Value<usize>,
+    total_tables: Value<usize>,
+    total_details: Value<usize>,
+    total_links: Value<usize>,
     has_error: Value<bool>,
+    /// Path of the open document and how far it is scrolled, published to the router so it
+    /// can keep track of history.
+    path: Value<String>,
+    offset: Value<i32>,
+    /// Whether the search prompt is open, and what is being searched for in it.
+    searching: Value<bool>,
+    query: Value<String>,
+    search_status: Value<String>,
+    /// Headings of the document, listed in the table of contents sidebar.
+    toc: Value<List<TocEntry>>,
+    toc_visible: Value<bool>,
+    /// Whether the heading picker is open, along with the prompt and headings it shows.
+    picking: Value<bool>,
+    picker_prompt: Value<String>,
+    picker: Value<List<TocEntry>>,
+    /// Heading of the section being read, how far through the document it is and how long
+    /// the document is, shown in the status bar.
+    section: Value<String>,
+    progress: Value<usize>,
+    words: Value<usize>,
+    reading_time: Value<usize>,
+    syntax_theme: Value<String>,
 }
 
 impl ViewerState {
-    pub fn new(tokens: impl Iterator<Item = InnerToken>) -> Self {
-        let tokens = tokens.collect::<Vec<_>>();
-        Self {
-            loading_document: Value::new(tokens.is_empty()),
-            has_error: Value::new(false),
-            total_tokens: Value::new(tokens.len()),
-            tokens: List::from_iter(tokens),
+    pub fn new(path: String, document: MarkdownDoc) -> Self {
+        let mut state = Self::default();
+        state.path.set(path);
+        state.set_document(document);
+        state
+    }
+
+    /// Footnote definitions are pulled out of the document so they can be rendered together
+    /// in a footnotes section at the end of it.
+    fn set_document(&mut self, document: MarkdownDoc) {
+        self.front_matter = Value::new(document.front_matter.into());
+        self.syntax_theme.set(Highlighter::shared().theme_name());
+
+        let (mut footnotes, mut tokens): (Vec<_>, Vec<_>) = document
+            .tokens
+            .into_iter()
+            .partition(|token| matches!(token, Token::FootnoteDefinition { .. }));
+
+        self.toc = List::from_iter(toc_entries(&mut tokens));
+
+        let mut links = 0;
+        let mut words = 0;
+        for token in tokens.iter_mut().chain(&mut footnotes) {
+            token.walk_mut(&mut |token| match token {
+                Token::Link { .. } => links += 1,
+                Token::Text { content, .. } | Token::Code(content) => {
+                    words += content.split_whitespace().count()
+                }
+                _ => {}
+            });
         }
+        self.words.set(words);
+        self.reading_time
+            .set(words.div_ceil(WORDS_PER_MINUTE).max(1));
+
+        self.loading_document.set(tokens.is_empty());
+        self.total_tokens.set(tokens.len());
+        self.total_footnotes.set(footnotes.len());
+        self.total_links.set(links);
+        self.blocks = List::from_iter((1..=tokens.len()).map(block_anchor));
+
+        self.tokens = List::from_iter(tokens.into_iter().map(Into::into));
+        self.footnotes = List::from_iter(footnotes.into_iter().map(Into::into));
+
+        // Tables get an id, however deeply they are nested, so they can be focused and
+        // scrolled on their own.
+        let mut tables = 0;
+        self.for_each_table(|table| {
+            if let InnerToken::Table { id, .. } = table {
+                tables += 1;
+                id.set(table_anchor(tables));
+            }
+        });
+        self.total_tables.set(tables);
+
+        // So do details, so the one on screen can be toggled.
+        let mut details = 0;
+        self.for_each_details(|section| {
+            if let InnerToken::Details { anchor, .. } = section {
+                details += 1;
+                anchor.set(details_anchor(details));
+            }
+        });
+        self.total_details.set(details);
+    }
+
+    /// Calls `f` with every top level block and footnote of the document, along with its id.
+    fn for_each_block(&mut self, mut f: impl FnMut(&str, &mut InnerToken)) {
+        let mut block = 0;
+        self.tokens.for_each(|token| {
+            block += 1;
+            f(&block_anchor(block), token);
+        });
+
+        self.footnotes.for_each(|footnote| {
+            let anchor = match footnote {
+                InnerToken::FootnoteDefinition { anchor, .. } => anchor.to_ref().clone(),
+                _ => String::new(),
+            };
+            f(&anchor, footnote);
+        });
+    }
+
+    /// Calls `f` with every link in the document and the id of the top level block, or
+    /// footnote, that it is in.
+    fn for_each_link(&mut self, mut f: impl FnMut(&str, &mut InnerToken)) {
+        self.for_each_block(|anchor, token| {
+            token.walk_mut(&mut |token| {
+                if let InnerToken::Link { .. } = token {
+                    f(anchor, token);
+                }
+            });
+        });
+    }
+
+    /// Calls `f` with every table in the document, including the ones nested in other blocks.
+    fn for_each_table(&mut self, mut f: impl FnMut(&mut InnerToken)) {
+        self.for_each_block(|_, token| {
+            token.walk_mut(&mut |token| {
+                if let InnerToken::Table { .. } = token {
+                    f(token);
+                }
+            });
+        });
+    }
+
+    /// Calls `f` with every `<details>` section in the document, including the ones nested in
+    /// other blocks.
+    fn for_each_details(&mut self, mut f: impl FnMut(&mut InnerToken)) {
+        self.for_each_block(|_, token| {
+            token.walk_mut(&mut |token| {
+                if let InnerToken::Details { .. } = token {
+                    f(token);
+                }
+            });
+        });
+    }
+
+    /// Shows or hides the blocks of sections nested in quotes, lists and other sections,
+    /// after sections were expanded or collapsed.
+    fn hide_collapsed_rows(&mut self) {
+        self.for_each_block(|_, token| token.walk_mut(&mut |token| token.hide_collapsed_rows()));
+    }
+
+    /// Highlights every match of `regex` in the document, or clears the highlights without
+    /// one, returning the id of the block each match is in.
+    fn highlight_matches(&mut self, regex: Option<&Regex>) -> Vec<String> {
+        let mut blocks = vec![];
+        let mut matches = 0;
+        self.for_each_block(|anchor, token| {
+            // Only what is on screen is searched, so tables' other layout and collapsed details
+            // are cleared.
+            token.walk_mut(&mut |token| token.highlight(None, &mut 0));
+            if regex.is_some() {
+                token.walk_shown_mut(&mut |token| token.highlight(regex, &mut matches));
+            }
+            blocks.resize(matches, anchor.to_string());
+        });
+        blocks
+    }
+
+    fn mark_current_match(&mut self, current: usize) {
+        self.for_each_block(|_, token| {
+            token.walk_shown_mut(&mut |token| token.mark_current_match(current));
+        });
     }
 }
 
+/// Messages the viewer is sent by the dashboard and the router.
+pub enum ViewerMessage {
+    /// Opens a document, starting at its top.
+    Open(String),
+    /// Opens a document from history, scrolled to where it was left.
+    Restore { path: String, offset: i32 },
+}
+
 #[derive(Default)]
-pub struct Viewer;
+pub struct Viewer {
+    /// Scroll offsets to return to after jumping somewhere else in the document.
+    jumps: Vec<i32>,
+    details_expanded: bool,
+    /// Index of the table that horizontal scrolling applies to, if any.
+    focused_table: Option<usize>,
+    selected_link: Option<usize>,
+    /// Path of the document being viewed, which relative links are resolved against.
+    path: Option<PathBuf>,
+    search: Option<Regex>,
+    /// Ids of the blocks the matches of the search are in, in order.
+    matches: Vec<String>,
+    current_match: Option<usize>,
+    /// Offset of the document when the current section and progress were last worked out, and
+    /// whether they have to be worked out again.
+    layout_offset: Option<i32>,
+    layout_stale: bool,
+    /// Heading of a document just opened to jump to once the document is laid out.
+    pending_anchor: Option<String>,
+    /// Offset of a document just restored from history to scroll to once it is laid out.
+    pending_offset: Option<i32>,
+    picker_query: String,
+    picker_selected: usize,
+}
+
+/// Id of the overflow the whole document is rendered in.
+const DOCUMENT: &str = "document";
+/// Id of an empty element after the document, telling where it ends.
+const END: &str = "end";
+/// Lines of the screen taken up by the status bar rather than the document.
+const STATUS_BAR_HEIGHT: i32 = 1;
+/// Ids of the overflows the table of contents and the heading picker are listed in.
+const TOC: &str = "toc";
+const PICKER: &str = "picker";
+
+fn element_y(elements: &mut Elements<'_, '_>, id: &str) -> Option<i32> {
+    let mut y = None;
+    elements
+        .by_attribute("id", id)
+        .first(|el, _| y = Some(el.get_pos().y));
+    y
+}
+
+fn scroll_to(overflow: &mut Overflow, offset: i32) {
+    let difference = offset - overflow.offset().y;
+    match difference > 0 {
+        true => overflow.scroll_down_by(difference),
+        false => overflow.scroll_up_by(difference.abs()),
+    }
+}
+
+fn is_on_screen(elements: &mut Elements<'_, '_>, id: &str, top: i32, height: i32) -> bool {
+    element_y(elements, id).is_some_and(|y| y >= top && y < top + height)
+}
+
+/// Lines each top level block, and footnote, takes up in the document, keyed by their ids.
+/// A block ends where the next one starts, the last one where the document ends.
+fn block_spans(
+    state: &mut ViewerState,
+    elements: &mut Elements<'_, '_>,
+) -> HashMap<String, Range<i32>> {
+    let mut anchors = vec![];
+    state.for_each_block(|anchor, _| anchors.push(anchor.to_string()));
+
+    let starts = anchors
+        .into_iter()
+        .filter_map(|anchor| Some((element_y(elements, &anchor)?, anchor)))
+        .collect::<Vec<_>>();
+    let end = element_y(elements, END).unwrap_or(i32::MAX);
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|(start, _)| *start)
+        .chain(std::iter::once(end));
+
+    starts
+        .iter()
+        .zip(ends)
+        .map(|((start, anchor), end)| (anchor.clone(), *start..end.max(start + 1)))
+        .collect()
+}
+
+/// Whether any of `span` is within the `height` lines of the document from `top`.
+fn overlaps_screen(span: &Range<i32>, top: i32, height: i32) -> bool {
+    span.start < top + height && span.end > top
+}
+
+/// Loads the document at `path`, showing an error in place of the document when it cannot be
+/// loaded.
+fn load_document(path: &Path, state: &mut ViewerState) -> Option<MarkdownDoc> {
+    let document = themark_fs::load_markdown(path).ok();
+    state.has_error.set(document.is_none());
+    document
+}
+
+/// Scrolls the list in the overflow with `id` so its entry at `index` is in the middle of it.
+fn scroll_list(elements: &mut Elements<'_, '_>, id: &str, index: usize, height: i32) {
+    elements.by_attribute("id", id).first(|el, _| {
+        scroll_to(el.to::<Overflow>(), (index as i32 - height / 2).max(0));
+    });
+}
+
+/// Scrolls the document so the element with `id` is at the top of the screen, returning the
+/// offset it was scrolled from.
+fn scroll_to_element(elements: &mut Elements<'_, '_>, id: &str) -> Option<i32> {
+    let top = element_y(elements, DOCUMENT)?;
+    let target = element_y(elements, id)?;
+
+    let mut previous = None;
+    elements.by_attribute("id", DOCUMENT).first(|el, _| {
+        let overflow = el.to::<Overflow>();
+        let offset = overflow.offset().y;
+        previous = Some(offset);
+        scroll_to(overflow, offset + target - top);
+    });
+    previous
+}
+
+impl Viewer {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            ..Self::default()
+        }
+    }
+
+    /// Jumps to the definition of the first footnote referenced on screen, remembering the
+    /// current position so it can be returned to.
+    fn jump_to_footnote(
+        &mut self,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        if state.total_footnotes.copy_value() == 0 {
+            return;
+        }
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+
+        // References are spans of text that cannot be found on screen themselves, so the
+        // first one in the blocks on screen is jumped from.
+        let spans = block_spans(state, elements);
+        let mut reference = None;
+        state.for_each_block(|anchor, token| {
+            let shown = spans
+                .get(anchor)
+                .is_some_and(|span| overlaps_screen(span, top, height));
+            if reference.is_some() || !shown {
+                return;
+            }
+            token.walk_shown_mut(&mut |token| {
+                if let InnerToken::FootnoteReference { index, .. } = token {
+                    reference.get_or_insert(index.copy_value());
+                }
+            });
+        });
+
+        let Some(index) = reference else { return };
+        if let Some(offset) = scroll_to_element(elements, &footnote_anchor(index)) {
+            self.jumps.push(offset);
+        }
+    }
+
+    /// Moves the focus to the next table, scrolling it into view. Moving past the last table
+    /// leaves no table focused.
+    fn focus_next_table(&mut self, state: &mut ViewerState, elements: &mut Elements<'_, '_>) {
+        let total_tables = state.total_tables.copy_value();
+        self.focused_table = match self.focused_table {
+            Some(index) if index < total_tables => Some(index + 1),
+            Some(_) => None,
+            None => (total_tables > 0).then_some(1),
+        };
+
+        let focused = self.focused_table.map(table_anchor);
+        state.for_each_table(|table| {
+            if let InnerToken::Table { id, focused: f, .. } = table {
+                f.set(focused.as_deref() == Some(id.to_ref().as_str()));
+            }
+        });
+
+        if let Some(id) = focused {
+            scroll_to_element(elements, &id);
+        }
+    }
+
+    fn toggle_table_wrap(&mut self, state: &mut ViewerState) {
+        let Some(focused) = self.focused_table.map(table_anchor) else {
+            return;
+        };
+
+        state.for_each_table(|table| {
+            if let InnerToken::Table { id, wrap, .. } = table {
+                if *id.to_ref() == focused {
+                    let wrapped = wrap.copy_value();
+                    wrap.set(!wrapped);
+                }
+            }
+        });
+
+        // Only the layout on screen is searched, so the one switched to is searched now.
+        self.refresh_search(state);
+    }
+
+    /// Switches to the next syntax theme built into the binary, highlighting the code blocks
+    /// of the document again with it.
+    fn cycle_syntax_theme(&mut self, state: &mut ViewerState) {
+        let highlighter = Highlighter::shared();
+        let names = theme_names();
+        let current = highlighter.theme_name();
+        let next = names
+            .iter()
+            .position(|name| *name == current)
+            .map_or(0, |index| (index + 1) % names.len());
+        let Ok(theme) = SyntaxTheme::load(&names[next]) else {
+            return;
+        };
+
+        highlighter.set_theme(theme);
+        state.syntax_theme.set(names[next].clone());
+        state.for_each_block(|_, token| {
+            token.walk_mut(&mut |token| token.highlight_code(highlighter));
+        });
+        self.refresh_search(state);
+    }
+
+    fn scroll_table(&mut self, code: KeyCode, elements: &mut Elements<'_, '_>) {
+        let Some(focused) = self.focused_table.map(table_anchor) else {
+            return;
+        };
+
+        elements
+            .by_attribute("id", focused.as_str())
+            .first(|el, _| {
+                let overflow = el.to::<Overflow>();
+                match code {
+                    KeyCode::Char('h') | KeyCode::Left => overflow.scroll_left_by(4),
+                    KeyCode::Char('l') | KeyCode::Right => overflow.scroll_right_by(4),
+                    _ => {}
+                }
+            });
+    }
+
+    /// Selects the next or previous link, starting from the first link on screen when none is
+    /// selected yet, and scrolls to it when it is out of view.
+    fn select_link(
+        &mut self,
+        forward: bool,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let total_links = state.total_links.copy_value();
+        if total_links == 0 {
+            return;
+        }
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+
+        let spans = block_spans(state, elements);
+        let index = match self.selected_link {
+            Some(index) if forward => index % total_links + 1,
+            Some(index) => (index + total_links - 2) % total_links + 1,
+            None => {
+                let mut on_screen = None;
+                state.for_each_link(|anchor, link| {
+                    if let InnerToken::Link { index, .. } = link {
+                        let shown = spans
+                            .get(anchor)
+                            .is_some_and(|span| overlaps_screen(span, top, height));
+                        if on_screen.is_none() && shown {
+                            on_screen = Some(index.copy_value());
+                        }
+                    }
+                });
+                on_screen.unwrap_or(1)
+            }
+        };
+
+        self.mark_selected_link(index, top, height, &spans, state, elements);
+    }
+
+    /// Selects a link in the block at `y` on screen, moving on to the next link of that block
+    /// when one of its links is selected already.
+    fn select_link_at(
+        &mut self,
+        y: i32,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+
+        let spans = block_spans(state, elements);
+        let block = spans.iter().find(|(_, span)| span.contains(&y));
+        let Some(block) = block.map(|(block, _)| block.clone()) else {
+            return;
+        };
+
+        // Tables hold their links more than once, as they are laid out twice.
+        let mut links = vec![];
+        state.for_each_link(|anchor, link| {
+            if let InnerToken::Link { index, .. } = link {
+                let index = index.copy_value();
+                if anchor == block && !links.contains(&index) {
+                    links.push(index);
+                }
+            }
+        });
+
+        let position = self
+            .selected_link
+            .and_then(|selected| links.iter().position(|index| *index == selected));
+        let index = match position {
+            Some(position) => links[(position + 1) % links.len()],
+            None => match links.first() {
+                Some(index) => *index,
+                None => return,
+            },
+        };
+
+        self.mark_selected_link(index, top, height, &spans, state, elements);
+    }
+
+    /// Highlights the link numbered `index`, scrolling to it when it is out of view.
+    fn mark_selected_link(
+        &mut self,
+        index: usize,
+        top: i32,
+        height: i32,
+        spans: &HashMap<String, Range<i32>>,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        self.selected_link = Some(index);
+
+        let mut block = None;
+        state.for_each_link(|anchor, link| {
+            if let InnerToken::Link {
+                index: i, selected, ..
+            } = link
+            {
+                let is_selected = i.copy_value() == index;
+                selected.set(is_selected);
+                if is_selected {
+                    block = Some(anchor.to_string());
+                }
+            }
+        });
+
+        if let Some(block) = block {
+            let shown = spans
+                .get(&block)
+                .is_some_and(|span| overlaps_screen(span, top, height));
+            if !shown {
+                scroll_to_element(elements, &block);
+            }
+        }
+    }
+
+    /// Handles a key while the search prompt is open, searching again as the query changes.
+    fn on_search_key(
+        &mut self,
+        code: KeyCode,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        match code {
+            KeyCode::Char(c) => {
+                state.query.to_mut().push(c);
+                self.search(height, state, elements);
+            }
+            KeyCode::Backspace => {
+                state.query.to_mut().pop();
+                self.search(height, state, elements);
+            }
+            KeyCode::Enter => state.searching.set(false),
+            KeyCode::Esc => {
+                state.searching.set(false);
+                self.clear_search(state);
+            }
+            _ => {}
+        }
+    }
+
+    /// Searches for the query, moving to the first match from the top of the screen on.
+    fn search(&mut self, height: i32, state: &mut ViewerState, elements: &mut Elements<'_, '_>) {
+        self.search = search_regex(&state.query.to_ref());
+        self.matches = state.highlight_matches(self.search.as_ref());
+        self.current_match = None;
+
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+        let below = self
+            .matches
+            .iter()
+            .position(|block| element_y(elements, block).is_some_and(|y| y >= top));
+
+        match self.matches.is_empty() {
+            true => self.set_search_status(state),
+            false => {
+                let index = below.map_or(1, |position| position + 1);
+                self.move_to_match(index, top, height, state, elements);
+            }
+        }
+    }
+
+    /// Moves to the next or previous match of the search, wrapping around at either end.
+    fn next_match(
+        &mut self,
+        forward: bool,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let total = self.matches.len();
+        if total == 0 {
+            return;
+        }
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+
+        let index = match self.current_match {
+            Some(index) if forward => index % total + 1,
+            Some(index) => (index + total - 2) % total + 1,
+            None => 1,
+        };
+        self.move_to_match(index, top, height, state, elements);
+    }
+
+    /// Highlights the match numbered `index` as the current one, scrolling to it when it is
+    /// out of view.
+    fn move_to_match(
+        &mut self,
+        index: usize,
+        top: i32,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        self.current_match = Some(index);
+        state.mark_current_match(index);
+        self.set_search_status(state);
+
+        let block = &self.matches[index - 1];
+        if !is_on_screen(elements, block, top, height) {
+            scroll_to_element(elements, block);
+        }
+    }
+
+    /// Highlights the matches of the search again after the text they were found in changed,
+    /// keeping the current match where possible.
+    fn refresh_search(&mut self, state: &mut ViewerState) {
+        if self.search.is_none() {
+            return;
+        }
+
+        self.matches = state.highlight_matches(self.search.as_ref());
+        self.current_match = self
+            .current_match
+            .map(|current| current.min(self.matches.len()))
+            .filter(|current| *current > 0);
+        if let Some(current) = self.current_match {
+            state.mark_current_match(current);
+        }
+        self.set_search_status(state);
+    }
+
+    fn set_search_status(&self, state: &mut ViewerState) {
+        let status = match (&self.search, self.current_match) {
+            (None, _) => String::new(),
+            (Some(_), None) if self.matches.is_empty() => String::from("no matches"),
+            (Some(_), None) => format!("{} matches", self.matches.len()),
+            (Some(_), Some(current)) => format!("match {current}/{}", self.matches.len()),
+        };
+        state.search_status.set(status);
+    }
+
+    fn clear_search(&mut self, state: &mut ViewerState) {
+        self.search = None;
+        self.matches.clear();
+        self.current_match = None;
+        state.highlight_matches(None);
+        self.set_search_status(state);
+    }
+
+    fn toggle_toc(&mut self, state: &mut ViewerState) {
+        let visible = state.toc_visible.copy_value();
+        state.toc_visible.set(!visible);
+        self.layout_stale = true;
+    }
+
+    /// Highlights the section of the table of contents that the top of the screen is in,
+    /// being the last heading at or above it.
+    fn update_section(
+        &mut self,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+
+        let mut current = None;
+        for (index, entry) in state.toc.to_ref().iter().enumerate() {
+            let anchor = entry.to_ref().anchor();
+            if element_y(elements, &anchor).is_some_and(|y| y <= top) {
+                current = Some(index);
+            }
+        }
+
+        let mut index = 0;
+        let mut section = String::new();
+        state.toc.for_each(|entry| {
+            if current == Some(index) {
+                section = entry.text();
+            }
+            entry.set_current(current == Some(index));
+            index += 1;
+        });
+        state.section.set(section);
+
+        if let Some(current) = current {
+            scroll_list(elements, TOC, current, height);
+        }
+    }
+
+    /// Works out how far through the document the screen is, from how far it is scrolled out
+    /// of how far it can be scrolled before its end comes into view.
+    fn update_progress(
+        &mut self,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let (Some(top), Some(end)) = (element_y(elements, DOCUMENT), element_y(elements, END))
+        else {
+            return;
+        };
+
+        let offset = self.layout_offset.unwrap_or_default();
+        let scrollable = end - top + offset - (height - STATUS_BAR_HEIGHT);
+        let progress = match scrollable > 0 {
+            true => (offset * 100 / scrollable).clamp(0, 100),
+            false => 100,
+        };
+        state.progress.set(progress as usize);
+    }
+
+    fn open_picker(
+        &mut self,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        self.picker_query.clear();
+        state.picking.set(true);
+        self.filter_headings(height, state, elements);
+    }
+
+    /// Lists the headings matching the query of the picker, best matches first, selecting the
+    /// first of them.
+    fn filter_headings(
+        &mut self,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let mut found = state
+            .toc
+            .to_ref()
+            .iter()
+            .enumerate()
+            .filter_map(|(position, entry)| {
+                let entry = entry.to_ref();
+                let score = fuzzy_score(&self.picker_query, &entry.text())?;
+                Some((Reverse(score), position, entry.unmarked()))
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(score, position, _)| (*score, *position));
+
+        while state.picker.len() > 0 {
+            state.picker.pop_back();
+        }
+        for (_, _, entry) in found {
+            state.picker.push_back(entry);
+        }
+
+        state.picker_prompt.set(format!("> {}", self.picker_query));
+        self.select_heading(0, height, state, elements);
+    }
+
+    /// Marks the heading at `index` in the picker as selected, keeping it in view.
+    fn select_heading(
+        &mut self,
+        index: usize,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        self.picker_selected = index;
+        let mut position = 0;
+        state.picker.for_each(|entry| {
+            entry.set_current(position == index);
+            position += 1;
+        });
+        scroll_list(elements, PICKER, index, height);
+    }
+
+    /// Handles a key while the heading picker is open, jumping to the selected heading on
+    /// enter and remembering the current position so it can be returned to.
+    fn on_picker_key(
+        &mut self,
+        code: KeyCode,
+        ctrl: bool,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let last = state.picker.len().saturating_sub(1);
+        let previous = self.picker_selected.saturating_sub(1);
+        let next = (self.picker_selected + 1).min(last);
+        match code {
+            KeyCode::Char('p') if ctrl => self.select_heading(previous, height, state, elements),
+            KeyCode::Char('n') if ctrl => self.select_heading(next, height, state, elements),
+            KeyCode::Up => self.select_heading(previous, height, state, elements),
+            KeyCode::Down => self.select_heading(next, height, state, elements),
+            KeyCode::Char(c) => {
+                self.picker_query.push(c);
+                self.filter_headings(height, state, elements);
+            }
+            KeyCode::Backspace => {
+                self.picker_query.pop();
+                self.filter_headings(height, state, elements);
+            }
+            KeyCode::Enter => {
+                state.picking.set(false);
+                let anchor = state
+                    .picker
+                    .to_ref()
+                    .iter()
+                    .nth(self.picker_selected)
+                    .map(|entry| entry.to_ref().anchor());
+                if let Some(offset) = anchor.and_then(|anchor| scroll_to_element(elements, &anchor))
+                {
+                    self.jumps.push(offset);
+                }
+            }
+            KeyCode::Esc => state.picking.set(false),
+            _ => {}
+        }
+    }
+
+    /// Follows the selected link. Headings are jumped to, remembering the current position
+    /// so it can be returned to, other markdown files are opened in the viewer and anything
+    /// else is handed to an external program.
+    fn follow_link(
+        &mut self,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+        context: &mut Context<'_, ViewerState>,
+    ) {
+        let Some(selected) = self.selected_link else {
+            return;
+        };
+
+        let mut uri = None;
+        state.for_each_link(|_, link| {
+            if let InnerToken::Link { index, uri: u, .. } = link {
+                if index.copy_value() == selected {
+                    uri = Some(u.to_ref().clone());
+                }
+            }
+        });
+        let Some(uri) = uri else { return };
+
+        let document = self.path.clone().unwrap_or_default();
+        match themark_fs::resolve_link(document, &uri) {
+            LinkTarget::Fragment(fragment) => {
+                if let Some(offset) = scroll_to_element(elements, &heading_anchor(&fragment)) {
+                    self.jumps.push(offset);
+                }
+            }
+            LinkTarget::Markdown { path, fragment } => {
+                // Like external links, a document that cannot be loaded simply does not open,
+                // leaving the one it is linked from on screen.
+                let Ok(document) = themark_fs::load_markdown(&path) else {
+                    return;
+                };
+                self.leave(state, elements, context);
+                self.open(path, document, state, elements);
+                self.pending_anchor = fragment.map(|fragment| heading_anchor(&fragment));
+                context.publish("open", |state| &state.path);
+            }
+            LinkTarget::External(target) => {
+                // There is nowhere to report a failure to, the link simply does not open.
+                let _ = themark_fs::open_external(&target);
+            }
+        }
+    }
+
+    /// Opens `document`, loaded from `path`, scrolled to its top.
+    fn open(
+        &mut self,
+        path: PathBuf,
+        document: MarkdownDoc,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        self.jumps.clear();
+        self.details_expanded = false;
+        self.focused_table = None;
+        self.selected_link = None;
+        self.search = None;
+        self.matches.clear();
+        self.current_match = None;
+        state.searching.set(false);
+        state.query.set(String::new());
+        state.search_status.set(String::new());
+        state.picking.set(false);
+        // Nothing is worked out from where elements are until the new document is laid out.
+        self.layout_offset = None;
+        self.layout_stale = true;
+        self.pending_anchor = None;
+        self.pending_offset = None;
+        state.has_error.set(false);
+        state.path.set(path.to_string_lossy().to_string());
+        self.path = Some(path);
+        state.set_document(document);
+        state.loading_document.set(false);
+
+        elements.by_attribute("id", DOCUMENT).first(|el, _| {
+            scroll_to(el.to::<Overflow>(), 0);
+        });
+    }
+
+    /// Lets the router know how far the document was scrolled before navigating away from it.
+    fn leave(
+        &self,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+        context: &mut Context<'_, ViewerState>,
+    ) {
+        elements.by_attribute("id", DOCUMENT).first(|el, _| {
+            state.offset.set(el.to::<Overflow>().offset().y);
+        });
+        context.publish("leave", |state| &state.offset);
+    }
+
+    /// Goes back to where the last jump was made from, or else lets the router know to go
+    /// back with `ident`, being either `back` or `escape`.
+    fn go_back(
+        &mut self,
+        ident: &str,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+        context: &mut Context<'_, ViewerState>,
+    ) {
+        if !self.jumps.is_empty() {
+            self.jump_back(elements);
+            return;
+        }
+
+        self.leave(state, elements, context);
+        context.publish(ident, |state| &state.path);
+    }
+
+    fn go_forward(
+        &mut self,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+        context: &mut Context<'_, ViewerState>,
+    ) {
+        self.leave(state, elements, context);
+        context.publish("forward", |state| &state.path);
+    }
+
+    /// Expands or collapses the first `<details>` section whose summary is on screen.
+    fn toggle_details(
+        &mut self,
+        height: i32,
+        state: &mut ViewerState,
+        elements: &mut Elements<'_, '_>,
+    ) {
+        let Some(top) = element_y(elements, DOCUMENT) else {
+            return;
+        };
+        let Some(toggled) = (1..=state.total_details.copy_value())
+            .map(details_anchor)
+            .find(|anchor| is_on_screen(elements, anchor, top, height))
+        else {
+            return;
+        };
+
+        state.for_each_details(|section| {
+            if let InnerToken::Details {
+                anchor, expanded, ..
+            } = section
+            {
+                if *anchor.to_ref() == toggled {
+                    let was_expanded = expanded.copy_value();
+                    expanded.set(!was_expanded);
+                }
+            }
+        });
+        self.details_changed(state);
+    }
+
+    /// Expands every `<details>` section, or collapses them all once they were expanded.
+    fn toggle_all_details(&mut self, state: &mut ViewerState) {
+        self.details_expanded = !self.details_expanded;
+        state.for_each_details(|section| {
+            if let InnerToken::Details { expanded, .. } = section {
+                expanded.set(self.details_expanded);
+            }
+        });
+        self.details_changed(state);
+    }
+
+    fn details_changed(&mut self, state: &mut ViewerState) {
+        state.hide_collapsed_rows();
+        // Collapsed details are not searched, so the ones expanded are searched now.
+        self.refresh_search(state);
+    }
+
+    fn jump_back(&mut self, elements: &mut Elements<'_, '_>) {
+        let Some(offset) = self.jumps.pop() else {
+            return;
+        };
+
+        elements.by_attribute("id", DOCUMENT).first(|el, _| {
+            scroll_to(el.to::<Overflow>(), offset);
+        });
+    }
+}
 
 impl Component for Viewer {
-    type Message = String;
+    type Message = ViewerMessage;
     type State = ViewerState;
 
     fn message(
         &mut self,
         message: Self::Message,
         state: &mut Self::State,
-        _: anathema::widgets::Elements<'_, '_>,
-        _: anathema::prelude::Context<'_, Self::State>,
+        mut elements: anathema::widgets::Elements<'_, '_>,
+        mut context: anathema::prelude::Context<'_, Self::State>,
     ) {
-        state.loading_document.set(true);
-        match themark_fs::load_markdown(message) {
-            Ok(tokens) => {
-                let tok: Vec<InnerToken> = tokens.into_iter().map(Into::into).collect::<Vec<_>>();
-                state.total_tokens.set(tok.len());
-                state.tokens = List::from_iter(tok);
-                state.loading_document.set(false);
+        match message {
+            ViewerMessage::Open(path) => {
+                let path = PathBuf::from(path);
+                if let Some(document) = load_document(&path, state) {
+                    self.open(path, document, state, &mut elements);
+                    context.publish("open", |state| &state.path);
+                }
             }
-            Err(_) => state.has_error.set(true),
-        };
+            ViewerMessage::Restore { path, offset } => {
+                let path = PathBuf::from(path);
+                if let Some(document) = load_document(&path, state) {
+                    self.open(path, document, state, &mut elements);
+                    self.pending_offset = Some(offset);
+                }
+            }
+        }
+    }
+
+    fn tick(
+        &mut self,
+        state: &mut Self::State,
+        mut elements: anathema::widgets::Elements<'_, '_>,
+        context: anathema::prelude::Context<'_, Self::State>,
+        _dt: Duration,
+    ) {
+        let mut offset = None;
+        elements.by_attribute("id", DOCUMENT).first(|el, _| {
+            offset = Some(el.to::<Overflow>().offset().y);
+        });
+
+        // Elements are only moved once the document is laid out again after scrolling, so the
+        // section and progress are worked out on the tick after the offset last changed.
+        if offset != self.layout_offset {
+            self.layout_offset = offset;
+            self.layout_stale = true;
+            return;
+        }
+
+        // The document may not even be on screen yet, e.g. right after going forward to it
+        // from the dashboard.
+        if self.layout_stale && offset.is_some() {
+            self.layout_stale = false;
+            // The section and progress are worked out once the scroll is laid out.
+            if let Some(offset) = self.pending_offset.take() {
+                elements.by_attribute("id", DOCUMENT).first(|el, _| {
+                    scroll_to(el.to::<Overflow>(), offset);
+                });
+                return;
+            }
+            if let Some(anchor) = self.pending_anchor.take() {
+                scroll_to_element(&mut elements, &anchor);
+                return;
+            }
+
+            let height = context.viewport.size().height as i32;
+            self.update_section(height, state, &mut elements);
+            self.update_progress(height, state, &mut elements);
+        }
     }
 
     fn on_mouse(
         &mut self,
         mouse: MouseEvent,
-        _: &mut Self::State,
+        state: &mut Self::State,
         mut elements: anathema::widgets::Elements<'_, '_>,
-        _: anathema::prelude::Context<'_, Self::State>,
+        context: anathema::prelude::Context<'_, Self::State>,
     ) {
-        elements.by_tag("overflow").first(|el, _| {
+        if let MouseState::Down(_) = mouse.state {
+            let height = context.viewport.size().height as i32;
+            self.select_link_at(mouse.pos().y, height, state, &mut elements);
+            return;
+        }
+
+        elements.by_attribute("id", DOCUMENT).first(|el, _| {
             let overflow = el.to::<Overflow>();
             match mouse.state {
                 MouseState::ScrollUp => overflow.scroll_up_by(3),
@@ -70,14 +1206,114 @@ impl Component for Viewer {
     fn on_key(
         &mut self,
         key: anathema::component::KeyEvent,
-        _: &mut Self::State,
+        state: &mut Self::State,
         mut elements: anathema::widgets::Elements<'_, '_>,
-        context: anathema::prelude::Context<'_, Self::State>,
+        mut context: anathema::prelude::Context<'_, Self::State>,
     ) {
+        let KeyEvent { code, ctrl, .. } = key;
         let height = context.viewport.size().height;
-        elements.by_tag("overflow").first(|el, _| {
+
+        // The search prompt and heading picker take every key while they are open.
+        if state.searching.copy_value() {
+            self.on_search_key(code, height as i32, state, &mut elements);
+            return;
+        }
+        if state.picking.copy_value() {
+            self.on_picker_key(code, ctrl, height as i32, state, &mut elements);
+            return;
+        }
+
+        match code {
+            KeyCode::Char('q') => {
+                context.stop_runtime();
+                return;
+            }
+            KeyCode::Char('o') if ctrl => {
+                self.go_back("back", state, &mut elements, &mut context);
+                return;
+            }
+            // Terminals send Ctrl-i as a tab.
+            KeyCode::Tab => {
+                self.go_forward(state, &mut elements, &mut context);
+                return;
+            }
+            KeyCode::Char('f') => {
+                self.jump_to_footnote(height as i32, state, &mut elements);
+                return;
+            }
+            KeyCode::Char('/') => {
+                state.searching.set(true);
+                state.query.set(String::new());
+                return;
+            }
+            KeyCode::Char('c') => {
+                self.toggle_toc(state);
+                return;
+            }
+            KeyCode::Char('s') => {
+                self.cycle_syntax_theme(state);
+                return;
+            }
+            KeyCode::Char('g') => {
+                self.open_picker(height as i32, state, &mut elements);
+                return;
+            }
+            KeyCode::Char(c @ ('n' | 'N')) => {
+                self.next_match(c == 'n', height as i32, state, &mut elements);
+                return;
+            }
+            // Escape clears the highlights of a search before it goes back.
+            KeyCode::Esc if self.search.is_some() => {
+                self.clear_search(state);
+                return;
+            }
+            KeyCode::Esc => {
+                self.go_back("escape", state, &mut elements, &mut context);
+                return;
+            }
+            KeyCode::Backspace => {
+                self.go_back("back", state, &mut elements, &mut context);
+                return;
+            }
+            KeyCode::Char('d') => {
+                self.toggle_details(height as i32, state, &mut elements);
+                return;
+            }
+            KeyCode::Char('D') => {
+                self.toggle_all_details(state);
+                return;
+            }
+            KeyCode::Char(c @ (']' | '[')) => {
+                self.select_link(c == ']', height as i32, state, &mut elements);
+                return;
+            }
+            KeyCode::Enter => {
+                self.follow_link(state, &mut elements, &mut context);
+                return;
+            }
+            KeyCode::Char('t') => {
+                self.focus_next_table(state, &mut elements);
+                return;
+            }
+            KeyCode::Char('w') => {
+                self.toggle_table_wrap(state);
+                return;
+            }
+            KeyCode::Char('h' | 'l') | KeyCode::Left | KeyCode::Right => {
+                self.scroll_table(code, &mut elements);
+                return;
+            }
+            KeyCode::Char('m') => {
+                let mut front_matter = state.front_matter.to_mut();
+                let expanded = front_matter.expanded.copy_value();
+                front_matter.expanded.set(!expanded);
+                return;
+            }
+            _ => {}
+        }
+
+        elements.by_attribute("id", DOCUMENT).first(|el, _| {
             let overflow = el.to::<Overflow>();
-            let KeyEvent { code, .. } = key;
             match code {
                 KeyCode::Char('j') | KeyCode::Down => overflow.scroll_down(),
                 KeyCode::Char('k') | KeyCode::Up => overflow.scroll_up(),